@@ -0,0 +1,73 @@
+//! Cluster groups and placement of instances and volumes
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::Deserialize;
+
+use crate::error::LxcError;
+
+/// Where in a cluster an instance or volume is created, passed as `--target`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClusterTarget {
+  /// Cluster member name
+  Member(String),
+  /// Cluster group, LXD picks a member of the group
+  Group(String),
+}
+
+impl ClusterTarget {
+  /// `--target` flag with its value
+  pub fn to_args(&self) -> Vec<String> {
+    vec!["--target".to_string(), self.to_string()]
+  }
+}
+
+impl fmt::Display for ClusterTarget {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ClusterTarget::Member(member) => write!(f, "{}", member),
+      ClusterTarget::Group(group) => write!(f, "@{}", group),
+    }
+  }
+}
+
+impl FromStr for ClusterTarget {
+  type Err = LxcError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let target = match s.strip_prefix('@') {
+      Some(group) => ClusterTarget::Group(group.to_string()),
+      None => ClusterTarget::Member(s.to_string()),
+    };
+
+    match &target {
+      ClusterTarget::Member(name) | ClusterTarget::Group(name) if name.is_empty() || name.contains(char::is_whitespace) => {
+        Err(LxcError::Invalid(format!("Invalid cluster target {}", s)))
+      },
+      _ => Ok(target),
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct ClusterGroup {
+  pub name: String,
+  #[serde(default)]
+  pub description: String,
+  /// Names of cluster members in the group
+  #[serde(default)]
+  pub members: Vec<String>,
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_target() {
+    assert_eq!("node1".parse::<ClusterTarget>().unwrap(), ClusterTarget::Member("node1".to_string()));
+    assert_eq!("@gpu".parse::<ClusterTarget>().unwrap().to_args(), vec!["--target".to_string(), "@gpu".to_string()]);
+    assert!("@".parse::<ClusterTarget>().is_err());
+  }
+}
@@ -0,0 +1,196 @@
+//! Commands run inside instances through the exec API of LXD.
+//!
+//! The output is recorded by LXD and read back from the instance logs, the exit code comes with the
+//! finished operation, so failures of LXD itself never look like a command exiting with 1.
+
+use std::collections::BTreeMap;
+
+use serde_json::{json, Value};
+
+/// Command to run in an instance, stdout and stderr are captured separately
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecCommand {
+  pub remote: String,
+  pub instance: String,
+  pub argv: Vec<String>,
+  pub env: BTreeMap<String, String>,
+  pub uid: Option<u32>,
+  pub gid: Option<u32>,
+  pub cwd: Option<String>,
+  /// Data written to stdin of the command, stdin is empty otherwise
+  pub stdin: Vec<u8>,
+  pub project: Option<String>,
+}
+
+impl ExecCommand {
+  /// `target` is `[<remote>:]<instance>`, local server by default and the only one `exec` reaches
+  pub fn new(target: &str, argv: &[&str]) -> Self {
+    let (remote, instance) = target.split_once(':').unwrap_or(("local", target));
+
+    Self {
+      remote: remote.to_string(),
+      instance: instance.to_string(),
+      argv: argv.iter().map(|arg| arg.to_string()).collect(),
+      env: BTreeMap::new(),
+      uid: None,
+      gid: None,
+      cwd: None,
+      stdin: Vec::new(),
+      project: None,
+    }
+  }
+
+  pub fn env(mut self, key: &str, value: &str) -> Self {
+    self.env.insert(key.to_string(), value.to_string());
+    self
+  }
+
+  pub fn uid(mut self, uid: u32) -> Self {
+    self.uid = Some(uid);
+    self
+  }
+
+  pub fn gid(mut self, gid: u32) -> Self {
+    self.gid = Some(gid);
+    self
+  }
+
+  /// Working directory inside the instance
+  pub fn cwd(mut self, cwd: &str) -> Self {
+    self.cwd = Some(cwd.to_string());
+    self
+  }
+
+  pub fn stdin(mut self, data: &[u8]) -> Self {
+    self.stdin = data.to_vec();
+    self
+  }
+
+  pub fn project(mut self, project: &str) -> Self {
+    self.project = Some(project.to_string());
+    self
+  }
+
+  /// API path in the project of the command, e.g. a log file LXD recorded
+  pub fn api_path(&self, path: &str) -> String {
+    match &self.project {
+      Some(project) => format!("{}{}project={}", path, if path.contains('?') { '&' } else { '?' }, project),
+      None => path.to_string(),
+    }
+  }
+
+  /// API path of the instance followed by `path`, e.g. `/exec`
+  pub fn instance_path(&self, path: &str) -> String {
+    self.api_path(&format!("/1.0/instances/{}{}", self.instance, path))
+  }
+
+  /// Body of `POST /1.0/instances/<name>/exec`, without a terminal so stdout and stderr stay apart.
+  ///
+  /// LXD gives recorded commands no stdin, with `stdin_file` pushed into the instance the command
+  /// runs through `sh` reading it instead.
+  pub fn to_request(&self, stdin_file: Option<&str>) -> Value {
+    let command: Vec<String> = match stdin_file {
+      Some(file) => ["sh", "-c", "exec \"$@\" < \"$0\"", file].iter().map(|arg| arg.to_string()).chain(self.argv.iter().cloned()).collect(),
+      None => self.argv.clone(),
+    };
+
+    let mut request = json!({
+      "command": command,
+      "environment": self.env,
+      "interactive": false,
+      "wait-for-websocket": false,
+      "record-output": true,
+    });
+
+    for (key, value) in [("user", self.uid), ("group", self.gid)] {
+      if let Some(value) = value {
+        request[key] = value.into();
+      }
+    }
+
+    if let Some(cwd) = &self.cwd {
+      request["cwd"] = cwd.as_str().into();
+    }
+
+    request
+  }
+
+  /// Headers of the file push writing stdin into the instance, readable only by the user of the command
+  pub fn stdin_file_headers(&self) -> Vec<(&'static str, String)> {
+    vec![
+      ("X-LXD-type", "file".to_string()),
+      ("X-LXD-mode", "0600".to_string()),
+      ("X-LXD-uid", self.uid.unwrap_or_default().to_string()),
+      ("X-LXD-gid", self.gid.unwrap_or_default().to_string()),
+      ("X-LXD-write", "overwrite".to_string()),
+    ]
+  }
+}
+
+/// Result of a command that ran in the instance, output is kept as the command wrote it
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExecOutput {
+  pub stdout: Vec<u8>,
+  pub stderr: Vec<u8>,
+  /// Exit code of the command itself, `128 + signal` if a signal killed it
+  pub exit_code: i32,
+}
+
+impl ExecOutput {
+  pub fn success(&self) -> bool {
+    self.exit_code == 0
+  }
+
+  /// Stdout as text, invalid UTF-8 replaced
+  pub fn stdout_text(&self) -> String {
+    String::from_utf8_lossy(&self.stdout).to_string()
+  }
+
+  /// Stderr as text, invalid UTF-8 replaced
+  pub fn stderr_text(&self) -> String {
+    String::from_utf8_lossy(&self.stderr).to_string()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn exec_request() {
+    let command = ExecCommand::new("web", &["uptime"]);
+
+    assert_eq!(command.remote, "local");
+    assert_eq!(command.instance_path("/exec"), "/1.0/instances/web/exec");
+    assert_eq!(command.to_request(None), json!({"command": ["uptime"], "environment": {}, "interactive": false, "wait-for-websocket": false, "record-output": true}));
+
+    let command = ExecCommand::new("db", &["psql", "-c", "select 1"])
+      .env("PGUSER", "postgres")
+      .uid(999)
+      .gid(999)
+      .cwd("/var/lib/postgresql")
+      .project("staging")
+      .stdin(b"\\q\n");
+
+    assert_eq!(command.stdin, b"\\q\n");
+    assert_eq!(command.instance_path("/exec"), "/1.0/instances/db/exec?project=staging");
+    assert_eq!(command.api_path("/1.0/instances/db/files?path=/tmp/in"), "/1.0/instances/db/files?path=/tmp/in&project=staging");
+
+    let request = command.to_request(Some("/tmp/in"));
+    assert_eq!(request["command"], json!(["sh", "-c", "exec \"$@\" < \"$0\"", "/tmp/in", "psql", "-c", "select 1"]));
+    assert_eq!(request["environment"]["PGUSER"], "postgres");
+    assert_eq!((request["user"].as_u64(), request["group"].as_u64()), (Some(999), Some(999)));
+    assert_eq!(request["cwd"], "/var/lib/postgresql");
+    assert!(command.stdin_file_headers().contains(&("X-LXD-uid", "999".to_string())));
+  }
+
+  #[test]
+  fn binary_output() {
+    let output = ExecOutput { stdout: vec![0xff, b'o', b'k'], stderr: b"warn\n".to_vec(), exit_code: 0 };
+
+    assert!(output.success());
+    assert_eq!(output.stdout, vec![0xff, b'o', b'k']);
+    assert_eq!(output.stdout_text(), "\u{fffd}ok");
+    assert_eq!(output.stderr_text(), "warn\n");
+  }
+}
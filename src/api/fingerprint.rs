@@ -0,0 +1,110 @@
+//! SHA-256 fingerprints of image files
+
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use sha2::{Digest, Sha256};
+
+use crate::error::LxcError;
+
+/// SHA-256 of the file as lowercase hex
+pub fn sha256_file(path: &Path) -> Result<String, LxcError> {
+  image_fingerprint(path, None)
+}
+
+/// Fingerprint LXD gives the image: SHA-256 of the metadata tarball followed by the rootfs of split images
+pub fn image_fingerprint(metadata: &Path, rootfs: Option<&Path>) -> Result<String, LxcError> {
+  let mut hasher = Sha256::new();
+
+  for path in Some(metadata).into_iter().chain(rootfs) {
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+  }
+
+  Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Recompute fingerprint of image files and compare it with the expected one.
+///
+/// `expected` may be a prefix of the fingerprint, as shown by `lxc image list`.
+pub fn verify_image_fingerprint(metadata: &Path, rootfs: Option<&Path>, expected: &str) -> Result<String, LxcError> {
+  let actual = image_fingerprint(metadata, rootfs)?;
+
+  if expected.len() < 12 || !actual.starts_with(&expected.to_lowercase()) {
+    return Err(LxcError::FingerprintMismatch { expected: expected.to_string(), actual });
+  }
+
+  Ok(actual)
+}
+
+/// Fingerprint encoded in the name of an exported file, e.g. `meta-<fingerprint>.tar.xz`
+pub fn fingerprint_from_file_name(path: &Path) -> Option<String> {
+  let name = path.file_name()?.to_str()?;
+  let stem = name.strip_prefix("meta-").unwrap_or(name).split('.').next()?;
+
+  if stem.len() == 64 && stem.chars().all(|c| c.is_ascii_hexdigit()) {
+    Some(stem.to_lowercase())
+  } else {
+    None
+  }
+}
+
+/// Files `lxc image export <image> <dir>/<name>` wrote: the unified tarball, or the metadata
+/// tarball with the rootfs of split images
+pub fn find_exported_files(dir: &Path, name: &str) -> Result<(PathBuf, Option<PathBuf>), LxcError> {
+  let mut names = Vec::new();
+
+  for entry in fs::read_dir(dir)? {
+    names.push(entry?.file_name().to_string_lossy().to_string());
+  }
+
+  names.sort();
+
+  let starts_with_name = |file: &str, prefix: &str| file.strip_prefix(prefix).map(|rest| rest.starts_with('.')).unwrap_or(false);
+
+  let metadata = names.iter().find(|file| starts_with_name(file, &format!("meta-{}", name)));
+  let main = names.iter().find(|file| starts_with_name(file, name));
+
+  match (metadata, main) {
+    (Some(metadata), Some(rootfs)) => Ok((dir.join(metadata), Some(dir.join(rootfs)))),
+    (None, Some(unified)) => Ok((dir.join(unified), None)),
+    _ => Err(LxcError::Invalid(format!("No exported image {} in {}", name, dir.display()))),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::api::test_dir::TestDir;
+
+  #[test]
+  fn verify_split_export() {
+    let dir = TestDir::new("verify");
+    fs::write(dir.join("meta-web.tar.xz"), b"metadata").unwrap();
+    fs::write(dir.join("web.squashfs"), b"rootfs").unwrap();
+
+    let (metadata, rootfs) = find_exported_files(&dir, "web").unwrap();
+    assert_eq!(rootfs, Some(dir.join("web.squashfs")));
+
+    let fingerprint = image_fingerprint(&metadata, rootfs.as_deref()).unwrap();
+    assert_eq!(verify_image_fingerprint(&metadata, rootfs.as_deref(), &fingerprint[..12]).unwrap(), fingerprint);
+
+    fs::write(dir.join("web.squashfs"), b"tampered").unwrap();
+    match verify_image_fingerprint(&metadata, rootfs.as_deref(), &fingerprint) {
+      Err(LxcError::FingerprintMismatch { expected, .. }) => assert_eq!(expected, fingerprint),
+      other => panic!("unexpected result: {:?}", other),
+    }
+
+    assert!(verify_image_fingerprint(&metadata, rootfs.as_deref(), "").is_err());
+    assert!(find_exported_files(&dir, "missing").is_err());
+  }
+
+  #[test]
+  fn fingerprint_in_file_name() {
+    let fingerprint = "fc1727a92249bb2b5d4c6b4d5e2b1a8b7f0a8f7f4b7e3a9c1d2e3f4a5b6c7d8e";
+
+    assert_eq!(fingerprint_from_file_name(Path::new(&format!("/tmp/meta-{}.tar.xz", fingerprint))), Some(fingerprint.to_string()));
+    assert_eq!(fingerprint_from_file_name(Path::new(&format!("{}.tar.gz", fingerprint))), Some(fingerprint.to_string()));
+    assert_eq!(fingerprint_from_file_name(Path::new("jammy.tar.gz")), None);
+  }
+}
@@ -1,70 +1,298 @@
-  use std::fmt;
-
-  #[derive(Debug)]
-  enum IsPublic {
-    YES,
-    NO
-  }
-  
-  #[derive(Debug)]
-  pub enum ImageType {
-    CONTAINER,
-    VIRTUAL_MACHINE
-  }
-  
-  #[derive(Debug, Clone, PartialEq)]
-  pub struct LxcImage {
-    alias: Option<String>,
-    fingerprint: String,
-    public: String,
-    description: String,
-    architecture: String,
-    tp: String,
-    size: String,
-    uploadtime: String,
-  }
-
-  impl LxcImage {
-    pub fn new(fingerprint: String) -> Self {
-       Self {
-          alias: None,
-          fingerprint: fingerprint.to_string(),
-          public: "no".to_string(),
-          description: "Ubuntu jammy amd64 (20220823_07:43)".to_string(),
-          architecture: "x86_64".to_string(),
-          tp: "CONTAINER".to_string(),
-          size: "113.26MB".to_string(),
-          uploadtime: "Aug 24, 2022 at 5:39am (UTC)".to_string()
-       }
+//! Images as returned by `lxc image list --format json`
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::Deserialize;
+
+use crate::error::LxcError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub enum ImageType {
+  #[default]
+  #[serde(rename = "container")]
+  Container,
+  #[serde(rename = "virtual-machine")]
+  VirtualMachine,
+}
+
+impl fmt::Display for ImageType {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      ImageType::Container => write!(f, "container"),
+      ImageType::VirtualMachine => write!(f, "virtual-machine"),
     }
+  }
+}
+
+/// Alias as listed by `lxc image alias list`, aliases embedded in images only have name and description
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ImageAlias {
+  pub name: String,
+  /// Fingerprint of the image the alias points to
+  pub target: String,
+  pub description: String,
+  #[serde(rename = "type")]
+  pub tp: ImageType,
+}
 
-    pub fn alias(mut self, alias: String) {
-       self.alias = Some(alias);
+/// Where a cached image was downloaded from
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ImageSource {
+  pub alias: String,
+  pub server: String,
+  pub protocol: String,
+  pub image_type: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LxcImage {
+  pub fingerprint: String,
+  pub aliases: Vec<ImageAlias>,
+  pub public: bool,
+  pub auto_update: bool,
+  pub cached: bool,
+  /// Architecture name as LXD knows it, e.g. `x86_64`
+  pub architecture: String,
+  #[serde(rename = "type")]
+  pub tp: ImageType,
+  /// Size in bytes
+  pub size: u64,
+  /// Image properties such as `os`, `release`, `variant` and `description`
+  pub properties: HashMap<String, String>,
+  pub profiles: Vec<String>,
+  pub update_source: Option<ImageSource>,
+  pub created_at: String,
+  pub uploaded_at: String,
+  pub expires_at: String,
+  pub last_used_at: String,
+}
+
+impl LxcImage {
+  pub fn new(fingerprint: String) -> Self {
+    Self {
+      fingerprint,
+      ..Default::default()
     }
   }
 
-  impl fmt::Display for LxcImage {
-      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-         write!(f, "{:?}", self.fingerprint);
+  pub fn alias(mut self, alias: String) -> Self {
+    self.aliases.push(ImageAlias { name: alias, target: self.fingerprint.to_string(), ..Default::default() });
+    self
+  }
+
+  pub fn property(&self, key: &str) -> Option<&str> {
+    self.properties.get(key).map(|value| value.as_str())
+  }
+
+  pub fn description(&self) -> &str {
+    self.property("description").unwrap_or_default()
+  }
+}
+
+impl fmt::Display for LxcImage {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{:?}", self.fingerprint)
+  }
+}
+
+/// Filters for image listing, every set field has to match
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ImageFilter {
+  architecture: Option<String>,
+  os: Option<String>,
+  release: Option<String>,
+  variant: Option<String>,
+  tp: Option<ImageType>,
+  public: Option<bool>,
+  alias_prefix: Option<String>,
+}
+
+impl ImageFilter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Matches either LXD architecture (`x86_64`) or the `architecture` property (`amd64`)
+  pub fn architecture(mut self, architecture: &str) -> Self {
+    self.architecture = Some(architecture.to_string());
+    self
+  }
+
+  pub fn os(mut self, os: &str) -> Self {
+    self.os = Some(os.to_string());
+    self
+  }
+
+  pub fn release(mut self, release: &str) -> Self {
+    self.release = Some(release.to_string());
+    self
+  }
+
+  pub fn variant(mut self, variant: &str) -> Self {
+    self.variant = Some(variant.to_string());
+    self
+  }
+
+  pub fn tp(mut self, tp: ImageType) -> Self {
+    self.tp = Some(tp);
+    self
+  }
+
+  pub fn public(mut self, public: bool) -> Self {
+    self.public = Some(public);
+    self
+  }
+
+  pub fn alias_prefix(mut self, prefix: &str) -> Self {
+    self.alias_prefix = Some(prefix.to_string());
+    self
+  }
+
+  pub fn matches(&self, image: &LxcImage) -> bool {
+    let property_matches = |key: &str, expected: &Option<String>| match expected {
+      Some(expected) => image.property(key).map(|value| value.eq_ignore_ascii_case(expected)).unwrap_or(false),
+      None => true,
+    };
+
+    let architecture_matches = match &self.architecture {
+      Some(expected) => image.architecture.eq_ignore_ascii_case(expected) || property_matches("architecture", &self.architecture),
+      None => true,
+    };
+
+    let alias_matches = match &self.alias_prefix {
+      Some(prefix) => image.aliases.iter().any(|alias| alias.name.starts_with(prefix.as_str())),
+      None => true,
+    };
+
+    architecture_matches
+      && alias_matches
+      && property_matches("os", &self.os)
+      && property_matches("release", &self.release)
+      && property_matches("variant", &self.variant)
+      && self.tp.map(|tp| tp == image.tp).unwrap_or(true)
+      && self.public.map(|public| public == image.public).unwrap_or(true)
+  }
+
+  /// Keep only images matching the filter
+  pub fn apply(&self, images: Vec<LxcImage>) -> Vec<LxcImage> {
+    images.into_iter().filter(|image| self.matches(image)).collect()
+  }
+}
+
+/// Find the one image with the alias, full fingerprint or fingerprint prefix.
+///
+/// Aliases win over fingerprints, a prefix shared by several images is an `AmbiguousImage` error.
+/// An alias naming both a container and a virtual machine image resolves to the container, as in `lxc`.
+pub fn resolve_image<'a>(images: &'a [LxcImage], name: &str) -> Result<&'a LxcImage, LxcError> {
+  let exact: Vec<&LxcImage> = images.iter().filter(|image| image.aliases.iter().any(|alias| alias.name == name) || image.fingerprint == name).collect();
 
-         Ok(())
-      }
+  if let Some(image) = exact.iter().find(|image| image.tp == ImageType::Container).or(exact.first()) {
+    return Ok(image);
   }
 
-  fn main() {
-    let ubuntu_jammy_amd64 = LxcImage::new("fc1727a92249".to_string());
+  let matches: Vec<&LxcImage> = images.iter().filter(|image| !name.is_empty() && image.fingerprint.starts_with(name)).collect();
 
-    println!("{}", ubuntu_jammy_amd64);
+  match matches.as_slice() {
+    [] => Err(LxcError::ImageNotFound(name.to_string())),
+    [image] => Ok(image),
+    _ => Err(LxcError::AmbiguousImage {
+      image: name.to_string(),
+      candidates: matches.iter().map(|image| image.fingerprint.to_string()).collect(),
+    }),
   }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const UBUNTU_JAMMY_AMD64: &str = r#"{
+    "aliases": [{"name": "ubuntu/jammy/amd64", "description": ""}, {"name": "ubuntu/22.04/amd64", "description": ""}],
+    "architecture": "x86_64",
+    "auto_update": true,
+    "cached": true,
+    "fingerprint": "fc1727a92249",
+    "public": false,
+    "size": 118762496,
+    "type": "container",
+    "profiles": ["default"],
+    "properties": {"architecture": "amd64", "description": "Ubuntu jammy amd64 (20220823_07:43)", "os": "Ubuntu", "release": "jammy", "variant": "default"},
+    "update_source": {"alias": "ubuntu/jammy/amd64", "server": "https://images.linuxcontainers.org", "protocol": "simplestreams", "image_type": "container"},
+    "uploaded_at": "2022-08-24T05:39:00Z",
+    "created_at": "2022-08-23T00:00:00Z",
+    "expires_at": "2027-04-21T00:00:00Z",
+    "last_used_at": "0001-01-01T00:00:00Z"
+  }"#;
+
+  #[test]
+  fn get_ubuntu_jammy_amd64() {
+    let ubuntu_jammy_amd64: LxcImage = serde_json::from_str(UBUNTU_JAMMY_AMD64).unwrap();
+
+    assert_eq!(ubuntu_jammy_amd64.fingerprint, "fc1727a92249");
+    assert_eq!(ubuntu_jammy_amd64.description(), "Ubuntu jammy amd64 (20220823_07:43)");
+    assert_eq!(ubuntu_jammy_amd64.architecture, "x86_64");
+    assert_eq!(ubuntu_jammy_amd64.tp, ImageType::Container);
+    assert_eq!(ubuntu_jammy_amd64.size, 118762496);
+    assert_eq!(ubuntu_jammy_amd64.update_source.unwrap().protocol, "simplestreams");
+    assert_eq!(LxcImage::new("fc1727a92249".to_string()).alias("jammy".to_string()).aliases[0].name, "jammy");
+  }
+
+  #[test]
+  fn filter_images() {
+    let image: LxcImage = serde_json::from_str(UBUNTU_JAMMY_AMD64).unwrap();
+
+    assert!(ImageFilter::new().matches(&image));
+    assert!(ImageFilter::new().os("ubuntu").release("jammy").architecture("amd64").matches(&image));
+    assert!(ImageFilter::new().architecture("x86_64").tp(ImageType::Container).public(false).matches(&image));
+    assert!(ImageFilter::new().alias_prefix("ubuntu/22").matches(&image));
+
+    assert!(!ImageFilter::new().os("debian").matches(&image));
+    assert!(!ImageFilter::new().tp(ImageType::VirtualMachine).matches(&image));
+    assert!(!ImageFilter::new().public(true).matches(&image));
+    assert!(!ImageFilter::new().alias_prefix("debian/").matches(&image));
+  }
+
+  #[test]
+  fn parse_alias_list() {
+    let aliases: Vec<ImageAlias> = serde_json::from_str(r#"[
+      {"name": "jammy", "description": "Golden image", "target": "fc1727a922490000", "type": "container"},
+      {"name": "jammy-vm", "description": "", "target": "bbbb000000000000", "type": "virtual-machine"}
+    ]"#).unwrap();
+
+    assert_eq!(aliases[0].target, "fc1727a922490000");
+    assert_eq!(aliases[0].description, "Golden image");
+    assert_eq!(aliases[1].tp, ImageType::VirtualMachine);
+  }
+
+  #[test]
+  fn resolve_prefix_and_alias() {
+    let images = vec![
+      LxcImage::new("fc1727a92249".to_string()).alias("jammy".to_string()),
+      LxcImage::new("fc17b0000000".to_string()),
+      LxcImage::new("0a1b2c3d4e5f".to_string()).alias("fc17".to_string()),
+    ];
 
-  #[cfg(test)]
-  mod tests {
-    use super::*;
+    assert_eq!(resolve_image(&images, "jammy").unwrap().fingerprint, "fc1727a92249");
+    assert_eq!(resolve_image(&images, "fc1727").unwrap().fingerprint, "fc1727a92249");
+    assert_eq!(resolve_image(&images, "fc17").unwrap().fingerprint, "0a1b2c3d4e5f");
+    assert!(matches!(resolve_image(&images, "").unwrap_err(), LxcError::ImageNotFound(_)));
 
-    #[test]
-    fn get_ubuntu_jammy_amd64() {
-      let ubuntu_jammy_amd64 = LxcImage::new("fc1727a92249".to_string());
+    let vm = LxcImage { tp: ImageType::VirtualMachine, ..LxcImage::new("bbbb00000000".to_string()).alias("ubuntu/jammy".to_string()) };
+    let container = LxcImage::new("aaaa00000000".to_string()).alias("ubuntu/jammy".to_string());
+    assert_eq!(resolve_image(&[vm.clone(), container], "ubuntu/jammy").unwrap().fingerprint, "aaaa00000000");
+    assert_eq!(resolve_image(&[vm], "ubuntu/jammy").unwrap().fingerprint, "bbbb00000000");
+    assert!(matches!(resolve_image(&images, "debian").unwrap_err(), LxcError::ImageNotFound(_)));
 
-      assert_eq!(ubuntu_jammy_amd64, LxcImage { fingerprint: "fc1727a92249".to_string(), public: "no".to_string(), description: "Ubuntu jammy amd64 (20220823_07:43)".to_string(), architecture: "x86_64".to_string(), tp: "CONTAINER".to_string(), size: "113.26MB".to_string(), uploadtime: "Aug 24, 2022 at 5:39am (UTC)".to_string() });
+    match resolve_image(&images[..2], "fc17").unwrap_err() {
+      LxcError::AmbiguousImage { image, candidates } => {
+        assert_eq!(image, "fc17");
+        assert_eq!(candidates, vec!["fc1727a92249", "fc17b0000000"]);
+      },
+      e => panic!("unexpected error {}", e),
     }
   }
+}
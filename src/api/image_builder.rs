@@ -0,0 +1,283 @@
+//! Build LXD-importable images from a root filesystem directory.
+//!
+//! Tarballs are packed with `tar`, squashfs rootfs with `mksquashfs`. Nothing here needs a
+//! running daemon, `image::import_built_lxc_image` imports the result.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use crate::api::fingerprint::image_fingerprint;
+use crate::api::image_metadata::{ImageMetadata, MetadataTemplate};
+use crate::api::time::now;
+use crate::error::LxcError;
+use crate::template::{path_arg, template_output};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageLayout {
+  /// One tarball with `metadata.yaml`, `templates/` and `rootfs/`
+  Unified,
+  /// Metadata tarball and separate rootfs tarball
+  SplitTarball,
+  /// Metadata tarball and squashfs rootfs
+  SplitSquashfs,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+  Gzip,
+  Xz,
+}
+
+impl Compression {
+  fn tar_flag(&self) -> &'static str {
+    match self {
+      Compression::Gzip => "-z",
+      Compression::Xz => "-J",
+    }
+  }
+
+  fn extension(&self) -> &'static str {
+    match self {
+      Compression::Gzip => "tar.gz",
+      Compression::Xz => "tar.xz",
+    }
+  }
+}
+
+/// Image written by `ImageBuilder`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuiltImage {
+  pub fingerprint: String,
+  pub metadata: ImageMetadata,
+  /// Metadata tarball of split images or the whole unified image
+  pub metadata_path: PathBuf,
+  pub rootfs_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImageBuilder {
+  rootfs: PathBuf,
+  metadata: ImageMetadata,
+  template_files: BTreeMap<String, String>,
+  layout: ImageLayout,
+  compression: Compression,
+}
+
+impl ImageBuilder {
+  /// Builder for the rootfs directory, architecture defaults to the host one
+  pub fn new(rootfs: &Path) -> Self {
+    Self {
+      rootfs: rootfs.to_path_buf(),
+      metadata: ImageMetadata {
+        architecture: std::env::consts::ARCH.to_string(),
+        ..Default::default()
+      },
+      template_files: BTreeMap::new(),
+      layout: ImageLayout::Unified,
+      compression: Compression::Gzip,
+    }
+  }
+
+  /// Kernel architecture name, e.g. `x86_64` or `aarch64`
+  pub fn architecture(mut self, architecture: &str) -> Self {
+    self.metadata.architecture = architecture.to_string();
+    self
+  }
+
+  /// Unix timestamp, defaults to the build time
+  pub fn creation_date(mut self, timestamp: i64) -> Self {
+    self.metadata.creation_date = timestamp;
+    self
+  }
+
+  pub fn expiry_date(mut self, timestamp: i64) -> Self {
+    self.metadata.expiry_date = Some(timestamp);
+    self
+  }
+
+  /// Image property such as `os`, `release`, `variant` or `description`
+  pub fn property(mut self, key: &str, value: &str) -> Self {
+    self.metadata.properties.insert(key.to_string(), value.to_string());
+    self
+  }
+
+  /// Template generating `path` in the instance, `content` is stored as `templates/<template.template>`
+  pub fn template(mut self, path: &str, template: MetadataTemplate, content: &str) -> Self {
+    self.template_files.insert(template.template.to_string(), content.to_string());
+    self.metadata.templates.insert(path.to_string(), template);
+    self
+  }
+
+  pub fn layout(mut self, layout: ImageLayout) -> Self {
+    self.layout = layout;
+    self
+  }
+
+  pub fn compression(mut self, compression: Compression) -> Self {
+    self.compression = compression;
+    self
+  }
+
+  /// Pack the image into the output directory, files are named like `lxc image export` names them
+  pub fn build(&self, output: &Path) -> Result<BuiltImage, LxcError> {
+    if !self.rootfs.is_dir() {
+      return Err(LxcError::Invalid(format!("Rootfs {} is not a directory", self.rootfs.display())));
+    }
+
+    let mut metadata = self.metadata.clone();
+
+    if metadata.creation_date == 0 {
+      metadata.creation_date = now();
+    }
+
+    fs::create_dir_all(output)?;
+
+    let staging = output.join(format!(".build-{}", process::id()));
+    let result = self.pack(&metadata, &staging, output);
+    let _ = fs::remove_dir_all(&staging);
+
+    result
+  }
+
+  fn pack(&self, metadata: &ImageMetadata, staging: &Path, output: &Path) -> Result<BuiltImage, LxcError> {
+    fs::create_dir_all(staging.join("templates"))?;
+    fs::write(staging.join("metadata.yaml"), metadata.to_yaml()?)?;
+
+    for (name, content) in &self.template_files {
+      fs::write(staging.join("templates").join(name), content)?;
+    }
+
+    let extension = self.compression.extension();
+    let metadata_tmp = staging.join(format!("metadata.{}", extension));
+    let mut args = vec![self.compression.tar_flag().to_string(), "--numeric-owner".to_string(), "-cf".to_string(), path_arg(&metadata_tmp), "-C".to_string(), path_arg(staging), "metadata.yaml".to_string(), "templates".to_string()];
+
+    let rootfs_tmp = match self.layout {
+      ImageLayout::Unified => {
+        // Entries of `-C rootfs .` start with `.`, rename them to `rootfs`, but leave symlink targets alone
+        args.extend(["--transform=s,^\\.,rootfs,S".to_string(), "-C".to_string(), path_arg(&self.rootfs), ".".to_string()]);
+        None
+      },
+      ImageLayout::SplitTarball => {
+        let rootfs = staging.join(format!("rootfs.{}", extension));
+        template_output("tar", vec![self.compression.tar_flag().to_string(), "--numeric-owner".to_string(), "-cf".to_string(), path_arg(&rootfs), "-C".to_string(), path_arg(&self.rootfs), ".".to_string()], "Failed to pack rootfs tarball")?;
+        Some((rootfs, extension))
+      },
+      ImageLayout::SplitSquashfs => {
+        let rootfs = staging.join("rootfs.squashfs");
+        template_output("mksquashfs", vec![path_arg(&self.rootfs), path_arg(&rootfs), "-noappend".to_string(), "-comp".to_string(), "xz".to_string()], "Failed to pack squashfs rootfs")?;
+        Some((rootfs, "squashfs"))
+      },
+    };
+
+    template_output("tar", args, "Failed to pack image metadata tarball")?;
+
+    let fingerprint = image_fingerprint(&metadata_tmp, rootfs_tmp.as_ref().map(|(rootfs, _)| rootfs.as_path()))?;
+
+    let (metadata_path, rootfs_path) = match rootfs_tmp {
+      None => (output.join(format!("{}.{}", fingerprint, extension)), None),
+      Some((rootfs, rootfs_extension)) => {
+        let rootfs_path = output.join(format!("{}.{}", fingerprint, rootfs_extension));
+        fs::rename(rootfs, &rootfs_path)?;
+
+        (output.join(format!("meta-{}.{}", fingerprint, extension)), Some(rootfs_path))
+      },
+    };
+
+    fs::rename(&metadata_tmp, &metadata_path)?;
+
+    Ok(BuiltImage { fingerprint, metadata: metadata.clone(), metadata_path, rootfs_path })
+  }
+}
+
+/// Check that image tarballs can be imported: `metadata.yaml` parses, templates it references are
+/// packed, unified images carry `rootfs/` and split images a non-empty rootfs
+pub fn validate_image_tarballs(metadata: &Path, rootfs: Option<&Path>) -> Result<ImageMetadata, LxcError> {
+  let listing = template_output("tar", vec!["-tf".to_string(), path_arg(metadata)], "Failed to list image tarball")?;
+  let entries: Vec<&str> = listing.lines().map(|entry| entry.trim_start_matches("./")).collect();
+
+  let image_metadata = ImageMetadata::from_tarball(metadata)?;
+
+  if image_metadata.architecture.is_empty() {
+    return Err(LxcError::Invalid("metadata.yaml has no architecture".to_string()));
+  }
+
+  for (path, template) in &image_metadata.templates {
+    let file = format!("templates/{}", template.template);
+
+    if !entries.contains(&file.as_str()) {
+      return Err(LxcError::Invalid(format!("Template {} for {} is missing from the image", file, path)));
+    }
+  }
+
+  let has_rootfs = entries.iter().any(|entry| entry.starts_with("rootfs/"));
+
+  match rootfs {
+    None if !has_rootfs => Err(LxcError::Invalid("Unified image has no rootfs directory".to_string())),
+    Some(_) if has_rootfs => Err(LxcError::Invalid("Metadata tarball of split image contains rootfs".to_string())),
+    Some(rootfs) if fs::metadata(rootfs)?.len() == 0 => Err(LxcError::Invalid(format!("Rootfs {} is empty", rootfs.display()))),
+    _ => Ok(image_metadata),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::api::image_metadata::TemplateTrigger;
+  use crate::api::test_dir::TestDir;
+  use std::os::unix::fs::symlink;
+
+  fn rootfs(name: &str) -> TestDir {
+    let dir = TestDir::new(name);
+
+    fs::create_dir_all(dir.join("rootfs/etc")).unwrap();
+    fs::write(dir.join("rootfs/etc/os-release"), "ID=custom\n").unwrap();
+    symlink("../etc/os-release", dir.join("rootfs/etc/release-link")).unwrap();
+
+    dir
+  }
+
+  fn builder(dir: &Path) -> ImageBuilder {
+    let hostname = MetadataTemplate::new("hostname.tpl", &[TemplateTrigger::Create, TemplateTrigger::Copy]);
+
+    ImageBuilder::new(&dir.join("rootfs"))
+      .architecture("x86_64")
+      .property("os", "custom")
+      .property("release", "1.0")
+      .template("/etc/hostname", hostname, "{{ container.name }}\n")
+  }
+
+  #[test]
+  fn build_unified_image() {
+    let dir = rootfs("build-unified");
+
+    let image = builder(&dir).build(&dir.join("out")).unwrap();
+
+    assert_eq!(image.rootfs_path, None);
+    assert_eq!(image.metadata_path, dir.join("out").join(format!("{}.tar.gz", image.fingerprint)));
+    assert_eq!(image.fingerprint, image_fingerprint(&image.metadata_path, None).unwrap());
+
+    let metadata = validate_image_tarballs(&image.metadata_path, None).unwrap();
+    assert_eq!(metadata.property("os"), Some("custom"));
+    assert!(metadata.creation_date > 0);
+
+    let listing = template_output("tar", vec!["-tvf".to_string(), path_arg(&image.metadata_path)], "").unwrap();
+    assert!(listing.contains("rootfs/etc/os-release"));
+    assert!(listing.contains("rootfs/etc/release-link -> ../etc/os-release"));
+    assert!(listing.contains("templates/hostname.tpl"));
+  }
+
+  #[test]
+  fn build_split_image() {
+    let dir = rootfs("build-split");
+
+    let image = builder(&dir).layout(ImageLayout::SplitTarball).compression(Compression::Xz).creation_date(1661241600).build(&dir.join("out")).unwrap();
+    let rootfs = image.rootfs_path.clone().unwrap();
+
+    assert!(image.metadata_path.ends_with(format!("meta-{}.tar.xz", image.fingerprint)));
+    assert_eq!(image.fingerprint, image_fingerprint(&image.metadata_path, Some(&rootfs)).unwrap());
+    assert_eq!(validate_image_tarballs(&image.metadata_path, Some(&rootfs)).unwrap().creation_date, 1661241600);
+    assert!(validate_image_tarballs(&image.metadata_path, None).is_err());
+  }
+}
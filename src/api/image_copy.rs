@@ -0,0 +1,177 @@
+//! Options of `lxc image copy` between any two remotes
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::api::image::ImageType;
+use crate::error::LxcError;
+
+/// How image data gets to the destination, passed as `--mode`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CopyMode {
+  /// Destination downloads the image from the source
+  #[default]
+  Pull,
+  /// Source sends the image to the destination
+  Push,
+  /// Image goes through the client
+  Relay,
+}
+
+impl fmt::Display for CopyMode {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    match self {
+      CopyMode::Pull => write!(f, "pull"),
+      CopyMode::Push => write!(f, "push"),
+      CopyMode::Relay => write!(f, "relay"),
+    }
+  }
+}
+
+impl FromStr for CopyMode {
+  type Err = LxcError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s {
+      "pull" => Ok(CopyMode::Pull),
+      "push" => Ok(CopyMode::Push),
+      "relay" => Ok(CopyMode::Relay),
+      _ => Err(LxcError::Invalid(format!("Unknown image copy mode {}", s))),
+    }
+  }
+}
+
+/// Copy of an image from one remote to another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageCopy {
+  pub source_remote: String,
+  /// Alias or fingerprint (prefix) on the source remote
+  pub image: String,
+  pub destination_remote: String,
+  pub aliases: Vec<String>,
+  pub copy_aliases: bool,
+  pub auto_update: bool,
+  pub public: bool,
+  pub tp: ImageType,
+  pub mode: Option<CopyMode>,
+  pub profiles: Vec<String>,
+  pub target_project: Option<String>,
+}
+
+impl ImageCopy {
+  pub fn new(source_remote: &str, image: &str, destination_remote: &str) -> Self {
+    Self {
+      source_remote: source_remote.to_string(),
+      image: image.to_string(),
+      destination_remote: destination_remote.to_string(),
+      aliases: Vec::new(),
+      copy_aliases: false,
+      auto_update: false,
+      public: false,
+      tp: ImageType::Container,
+      mode: None,
+      profiles: Vec::new(),
+      target_project: None,
+    }
+  }
+
+  /// Alias to create on the destination, can be given several times
+  pub fn alias(mut self, alias: &str) -> Self {
+    self.aliases.push(alias.to_string());
+    self
+  }
+
+  /// Create the aliases the image has on the source
+  pub fn copy_aliases(mut self, copy_aliases: bool) -> Self {
+    self.copy_aliases = copy_aliases;
+    self
+  }
+
+  /// Keep the copy updated from the source
+  pub fn auto_update(mut self, auto_update: bool) -> Self {
+    self.auto_update = auto_update;
+    self
+  }
+
+  pub fn public(mut self, public: bool) -> Self {
+    self.public = public;
+    self
+  }
+
+  /// Copy the virtual machine image of the alias instead of the container one
+  pub fn vm(mut self, vm: bool) -> Self {
+    self.tp = if vm { ImageType::VirtualMachine } else { ImageType::Container };
+    self
+  }
+
+  pub fn mode(mut self, mode: CopyMode) -> Self {
+    self.mode = Some(mode);
+    self
+  }
+
+  /// Profile to apply to new instances of the image, can be given several times
+  pub fn profile(mut self, profile: &str) -> Self {
+    self.profiles.push(profile.to_string());
+    self
+  }
+
+  /// Project on the destination remote
+  pub fn target_project(mut self, project: &str) -> Self {
+    self.target_project = Some(project.to_string());
+    self
+  }
+
+  /// Arguments of `lxc`
+  pub fn to_args(&self) -> Vec<String> {
+    let mut args = vec!["image".to_string(), "copy".to_string(), format!("{}:{}", self.source_remote, self.image), format!("{}:", self.destination_remote)];
+
+    for alias in &self.aliases {
+      args.extend(["--alias".to_string(), alias.to_string()]);
+    }
+
+    for (flag, set) in [("--copy-aliases", self.copy_aliases), ("--auto-update", self.auto_update), ("--public", self.public), ("--vm", self.tp == ImageType::VirtualMachine)] {
+      if set {
+        args.push(flag.to_string());
+      }
+    }
+
+    if let Some(mode) = self.mode {
+      args.extend(["--mode".to_string(), mode.to_string()]);
+    }
+
+    for profile in &self.profiles {
+      args.extend(["--profile".to_string(), profile.to_string()]);
+    }
+
+    if let Some(project) = &self.target_project {
+      args.extend(["--target-project".to_string(), project.to_string()]);
+    }
+
+    args
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn copy_args() {
+    assert_eq!(ImageCopy::new("local", "jammy", "site-b").to_args(), vec!["image", "copy", "local:jammy", "site-b:"]);
+
+    let copy = ImageCopy::new("images", "ubuntu/jammy", "local")
+      .alias("jammy")
+      .alias("ubuntu-lts")
+      .copy_aliases(true)
+      .auto_update(true)
+      .public(true)
+      .vm(true)
+      .mode("relay".parse().unwrap())
+      .profile("default")
+      .profile("web")
+      .target_project("staging");
+
+    assert_eq!(copy.to_args().join(" "), "image copy images:ubuntu/jammy local: --alias jammy --alias ubuntu-lts --copy-aliases --auto-update --public --vm --mode relay --profile default --profile web --target-project staging");
+    assert!("sideways".parse::<CopyMode>().is_err());
+  }
+}
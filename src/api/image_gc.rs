@@ -0,0 +1,183 @@
+//! Garbage collection of local images no instance or alias uses
+
+use std::collections::{BTreeMap, HashSet};
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::api::image::LxcImage;
+use crate::api::time::parse_rfc3339;
+
+/// Which unreferenced images are removed
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GcPolicy {
+  /// Only report images, delete nothing
+  pub dry_run: bool,
+  /// Keep images used or uploaded more recently than this
+  pub min_age: Option<Duration>,
+  /// Keep this many newest images of every alias the images were downloaded from
+  pub keep_latest_per_alias: usize,
+}
+
+/// Unreferenced image and what the policy decided about it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GcCandidate {
+  /// Project whose image store holds the image
+  pub project: String,
+  pub fingerprint: String,
+  /// Size in bytes
+  pub size: u64,
+  /// Empty if the image was never used
+  pub last_used_at: String,
+  pub uploaded_at: String,
+  /// Alias the image was downloaded from, or its os/release/arch/variant
+  pub group: String,
+  /// The policy allows deleting the image
+  pub collectable: bool,
+}
+
+/// Projects with their own image store, from `/1.0/projects?recursion=1`.
+///
+/// Projects without `features.images` use the images of the `default` project.
+pub fn image_projects(projects: &[Value]) -> Vec<String> {
+  projects.iter()
+    .filter(|project| project["name"] == "default" || project["config"]["features.images"] == "true")
+    .filter_map(|project| project["name"].as_str())
+    .map(|name| name.to_string())
+    .collect()
+}
+
+/// Fingerprints in `volatile.base_image` of instances as listed by `lxc list --format json`
+pub fn base_images(instances: &[Value]) -> HashSet<String> {
+  instances.iter()
+    .filter_map(|instance| instance["config"]["volatile.base_image"].as_str())
+    .map(|fingerprint| fingerprint.to_string())
+    .collect()
+}
+
+/// Images without aliases that no instance was created from
+pub fn unreferenced_images<'a>(images: &'a [LxcImage], base_images: &HashSet<String>) -> Vec<&'a LxcImage> {
+  images.iter()
+    .filter(|image| image.aliases.is_empty() && !base_images.contains(&image.fingerprint))
+    .collect()
+}
+
+fn gc_group(image: &LxcImage) -> String {
+  match image.update_source.as_ref().filter(|source| !source.alias.is_empty()) {
+    Some(source) => format!("{}/{}", source.alias, image.architecture),
+    None => {
+      let property = |key: &str| image.property(key).unwrap_or_default().to_lowercase();
+
+      format!("{}/{}/{}/{}", property("os"), property("release"), image.architecture, property("variant"))
+    },
+  }
+}
+
+/// Unix timestamp of the last use of the image, its upload for never used images
+fn last_activity(image: &LxcImage) -> i64 {
+  let uploaded = parse_rfc3339(&image.uploaded_at).unwrap_or_default();
+
+  parse_rfc3339(&image.last_used_at).map(|used| used.max(uploaded)).unwrap_or(uploaded)
+}
+
+/// Decide which unreferenced images of the project the policy collects, `now` is a unix timestamp
+pub fn plan_gc(project: &str, images: &[LxcImage], base_images: &HashSet<String>, policy: &GcPolicy, now: i64) -> Vec<GcCandidate> {
+  let mut groups: BTreeMap<String, Vec<&LxcImage>> = BTreeMap::new();
+
+  for image in unreferenced_images(images, base_images) {
+    groups.entry(gc_group(image)).or_default().push(image);
+  }
+
+  let mut candidates = Vec::new();
+
+  for (group, mut members) in groups {
+    // Newest first, so the first ones are kept
+    members.sort_by_key(|image| std::cmp::Reverse(parse_rfc3339(&image.created_at).unwrap_or_default().max(parse_rfc3339(&image.uploaded_at).unwrap_or_default())));
+
+    for (position, image) in members.into_iter().enumerate() {
+      let old_enough = policy.min_age.map(|age| now - last_activity(image) >= age.as_secs() as i64).unwrap_or(true);
+      let never_used = parse_rfc3339(&image.last_used_at).map(|used| used <= 0).unwrap_or(true);
+
+      candidates.push(GcCandidate {
+        project: project.to_string(),
+        fingerprint: image.fingerprint.to_string(),
+        size: image.size,
+        last_used_at: if never_used { String::new() } else { image.last_used_at.to_string() },
+        uploaded_at: image.uploaded_at.to_string(),
+        group: group.to_string(),
+        collectable: old_enough && position >= policy.keep_latest_per_alias,
+      });
+    }
+  }
+
+  candidates
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::api::image::{ImageAlias, ImageSource};
+
+  fn image(fingerprint: &str, uploaded_at: &str, last_used_at: &str) -> LxcImage {
+    LxcImage {
+      fingerprint: fingerprint.to_string(),
+      architecture: "x86_64".to_string(),
+      size: 100,
+      uploaded_at: uploaded_at.to_string(),
+      created_at: uploaded_at.to_string(),
+      last_used_at: last_used_at.to_string(),
+      update_source: Some(ImageSource { alias: "ubuntu/jammy".to_string(), ..Default::default() }),
+      ..Default::default()
+    }
+  }
+
+  #[test]
+  fn plan_collection() {
+    let never = "0001-01-01T00:00:00Z";
+    let mut aliased = image("aliased", "2022-01-01T00:00:00Z", never);
+    aliased.aliases.push(ImageAlias { name: "golden".to_string(), ..Default::default() });
+
+    let images = vec![
+      image("old", "2022-01-01T00:00:00Z", never),
+      image("newer", "2022-06-01T00:00:00Z", "2022-06-02T00:00:00Z"),
+      image("newest", "2022-08-01T00:00:00Z", never),
+      image("used", "2022-01-01T00:00:00Z", never),
+      aliased,
+    ];
+
+    let instances: Vec<Value> = serde_json::from_str(r#"[{"name": "web", "config": {"volatile.base_image": "used"}}, {"name": "db", "config": {}}]"#).unwrap();
+    let base = base_images(&instances);
+
+    assert_eq!(unreferenced_images(&images, &base).len(), 3);
+
+    let now = parse_rfc3339("2022-08-15T00:00:00Z").unwrap();
+    let policy = GcPolicy { dry_run: true, min_age: Some(Duration::from_secs(30 * 86400)), keep_latest_per_alias: 1 };
+    let plan = plan_gc("default", &images, &base, &policy, now);
+
+    let decision = |fingerprint: &str| plan.iter().find(|candidate| candidate.fingerprint == fingerprint).unwrap();
+
+    assert_eq!(plan.len(), 3);
+    assert!(!decision("newest").collectable);
+    assert!(decision("newer").collectable);
+    assert!(decision("old").collectable);
+    assert_eq!(decision("old").last_used_at, "");
+    assert_eq!(decision("old").group, "ubuntu/jammy/x86_64");
+    assert_eq!(decision("old").project, "default");
+
+    let recent = GcPolicy { min_age: Some(Duration::from_secs(90 * 86400)), ..Default::default() };
+    let plan = plan_gc("default", &images, &base, &recent, now);
+    assert_eq!(plan.iter().filter(|candidate| candidate.collectable).count(), 1);
+  }
+
+  #[test]
+  fn projects_with_own_images() {
+    let projects: Vec<Value> = serde_json::from_str(r#"[
+      {"name": "default", "config": {"features.images": "true"}},
+      {"name": "dev", "config": {"features.images": "true"}},
+      {"name": "shared", "config": {"features.images": "false"}},
+      {"name": "legacy", "config": {}}
+    ]"#).unwrap();
+
+    assert_eq!(image_projects(&projects), vec!["default", "dev"]);
+  }
+}
@@ -0,0 +1,160 @@
+//! Options and progress of `lxc image import`.
+//!
+//! `lxc` prints progress only to a terminal, imports run it under `script` to get a pseudo terminal.
+
+use std::path::{Path, PathBuf};
+
+/// Files or location of the image to import
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ImageImportSource {
+  /// Tarball with metadata and rootfs
+  Unified(PathBuf),
+  /// Metadata tarball with the rootfs tarball, squashfs or qcow2 disk of virtual machines
+  Split { metadata: PathBuf, rootfs: PathBuf },
+  /// Unified image on HTTP(S) server, downloaded before the import
+  Url(String),
+}
+
+/// Import of an image to a remote
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageImport {
+  pub source: ImageImportSource,
+  pub remote: String,
+  pub aliases: Vec<String>,
+  pub public: bool,
+  pub properties: Vec<(String, String)>,
+}
+
+impl ImageImport {
+  /// Import to the local server
+  pub fn new(source: ImageImportSource) -> Self {
+    Self {
+      source,
+      remote: "local".to_string(),
+      aliases: Vec::new(),
+      public: false,
+      properties: Vec::new(),
+    }
+  }
+
+  pub fn remote(mut self, remote: &str) -> Self {
+    self.remote = remote.to_string();
+    self
+  }
+
+  /// Alias of the image, can be given several times
+  pub fn alias(mut self, alias: &str) -> Self {
+    self.aliases.push(alias.to_string());
+    self
+  }
+
+  pub fn public(mut self, public: bool) -> Self {
+    self.public = public;
+    self
+  }
+
+  pub fn property(mut self, key: &str, value: &str) -> Self {
+    self.properties.push((key.to_string(), value.to_string()));
+    self
+  }
+
+  /// Arguments of `lxc` importing the local files
+  pub fn to_args(&self, metadata: &Path, rootfs: Option<&Path>) -> Vec<String> {
+    let mut args = vec!["image".to_string(), "import".to_string(), metadata.to_string_lossy().to_string()];
+    args.extend(rootfs.map(|rootfs| rootfs.to_string_lossy().to_string()));
+    args.push(format!("{}:", self.remote));
+
+    for alias in &self.aliases {
+      args.extend(["--alias".to_string(), alias.to_string()]);
+    }
+
+    if self.public {
+      args.push("--public".to_string());
+    }
+
+    args.extend(self.properties.iter().map(|(key, value)| format!("{}={}", key, value)));
+
+    args
+  }
+}
+
+/// Arguments of `script` running the command under a pseudo terminal, with its exit code kept
+pub fn pty_args(command: &str, args: &[String]) -> Vec<String> {
+  let quoted: Vec<String> = Some(command).into_iter().chain(args.iter().map(|arg| arg.as_str()))
+    .map(|arg| format!("'{}'", arg.replace('\'', "'\\''")))
+    .collect();
+
+  vec!["-qefc".to_string(), quoted.join(" "), "/dev/null".to_string()]
+}
+
+/// Progress line printed by lxc, e.g. `Transferring image: 45% (12.34MB/s)`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportProgress {
+  pub stage: String,
+  pub percent: Option<u8>,
+  /// Transfer rate as lxc prints it
+  pub rate: Option<String>,
+}
+
+/// Parse progress line, `None` for lines which are not progress updates
+pub fn parse_progress(line: &str) -> Option<ImportProgress> {
+  let (stage, rest) = line.trim().split_once(": ")?;
+  let (percent, rest) = rest.split_once('%')?;
+
+  let rate = rest.trim()
+    .strip_prefix('(')
+    .and_then(|rate| rate.strip_suffix(')'))
+    .map(|rate| rate.to_string());
+
+  Some(ImportProgress {
+    stage: stage.to_string(),
+    percent: percent.trim().parse().ok(),
+    rate,
+  })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn import_args() {
+    let import = ImageImport::new(ImageImportSource::Unified(PathBuf::from("/tmp/image.tar.gz")));
+    assert_eq!(import.to_args(Path::new("/tmp/image.tar.gz"), None), vec!["image", "import", "/tmp/image.tar.gz", "local:"]);
+
+    let import = ImageImport::new(ImageImportSource::Split { metadata: PathBuf::from("meta.tar.xz"), rootfs: PathBuf::from("disk.qcow2") })
+      .remote("site-b")
+      .alias("jammy-vm")
+      .public(true)
+      .property("os", "Ubuntu");
+
+    assert_eq!(import.to_args(Path::new("meta.tar.xz"), Some(Path::new("disk.qcow2"))).join(" "), "image import meta.tar.xz disk.qcow2 site-b: --alias jammy-vm --public os=Ubuntu");
+  }
+
+  #[test]
+  fn quote_pty_command() {
+    let args = pty_args("lxc", &["image".to_string(), "import".to_string(), "it's here.tar.gz".to_string()]);
+
+    assert_eq!(args, vec!["-qefc", "'lxc' 'image' 'import' 'it'\\''s here.tar.gz'", "/dev/null"]);
+  }
+
+  /// lxc redraws the progress on one terminal line, blanking it before shorter updates
+  #[test]
+  fn progress_under_pty() {
+    let output = "\rTransferring image: 5% (1.23MB/s)\r                                  \rTransferring image: 100% (12.34MB/s)\r                                  \rImage imported with fingerprint: 8d1b7f2e33a5\n";
+    let script = format!("[ -t 1 ] && printf '%s' '{}'", output);
+    let mut updates = Vec::new();
+
+    crate::template::template_lines("script", pty_args("sh", &["-c".to_string(), script]), &mut |line| updates.extend(parse_progress(line)), "").unwrap();
+
+    assert_eq!(updates.iter().map(|update| update.percent).collect::<Vec<_>>(), vec![Some(5), Some(100)]);
+    assert_eq!(updates[1].rate.as_deref(), Some("12.34MB/s"));
+  }
+
+  #[test]
+  fn progress_lines() {
+    assert_eq!(parse_progress("Transferring image: 45% (12.34MB/s)"), Some(ImportProgress { stage: "Transferring image".to_string(), percent: Some(45), rate: Some("12.34MB/s".to_string()) }));
+    assert_eq!(parse_progress("Transferring image: 100%").unwrap().rate, None);
+    assert_eq!(parse_progress("Image imported with fingerprint: 8d1b7f2e33a5"), None);
+  }
+}
@@ -0,0 +1,129 @@
+//! Image `metadata.yaml`
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::LxcError;
+use crate::template::template_output;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ImageMetadata {
+  /// Kernel architecture name, e.g. `x86_64`
+  pub architecture: String,
+  /// Unix timestamp
+  pub creation_date: i64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub expiry_date: Option<i64>,
+  #[serde(default)]
+  pub properties: BTreeMap<String, String>,
+  /// Templates keyed by the path of the generated file in the instance
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub templates: BTreeMap<String, MetadataTemplate>,
+}
+
+/// Event on which a template is applied
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TemplateTrigger {
+  /// Instance is created from the image
+  Create,
+  /// Instance is copied from another instance
+  Copy,
+  /// Instance is started
+  Start,
+  /// Instance is renamed
+  Rename,
+  /// Trigger this crate doesn't know yet, kept as LXD names it
+  #[serde(untagged)]
+  Other(String),
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MetadataTemplate {
+  pub when: Vec<TemplateTrigger>,
+  #[serde(default)]
+  pub create_only: bool,
+  /// File name in the `templates` directory of the image
+  pub template: String,
+  #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+  pub properties: BTreeMap<String, String>,
+}
+
+impl MetadataTemplate {
+  pub fn new(template: &str, when: &[TemplateTrigger]) -> Self {
+    Self {
+      when: when.to_vec(),
+      template: template.to_string(),
+      ..Default::default()
+    }
+  }
+
+  pub fn applies_on(&self, trigger: TemplateTrigger) -> bool {
+    self.when.contains(&trigger)
+  }
+}
+
+impl ImageMetadata {
+  pub fn from_yaml(yaml: &str) -> Result<Self, LxcError> {
+    Ok(serde_yaml::from_str(yaml)?)
+  }
+
+  pub fn to_yaml(&self) -> Result<String, LxcError> {
+    Ok(serde_yaml::to_string(self)?)
+  }
+
+  /// Read `metadata.yaml` from metadata or unified image tarball
+  pub fn from_tarball(path: &Path) -> Result<Self, LxcError> {
+    let tarball = path.to_string_lossy().to_string();
+
+    let yaml = template_output("tar", vec!["-xOf".to_string(), tarball.to_string(), "metadata.yaml".to_string()], "Failed to read metadata.yaml from image tarball")
+      .or_else(|_| template_output("tar", vec!["-xOf".to_string(), tarball, "./metadata.yaml".to_string()], "Failed to read metadata.yaml from image tarball"))?;
+
+    Self::from_yaml(&yaml)
+  }
+
+  pub fn property(&self, key: &str) -> Option<&str> {
+    self.properties.get(key).map(|value| value.as_str())
+  }
+
+  /// Paths of files generated on the trigger
+  pub fn templates_on(&self, trigger: TemplateTrigger) -> Vec<&str> {
+    self.templates.iter()
+      .filter(|(_, template)| template.applies_on(trigger.clone()))
+      .map(|(path, _)| path.as_str())
+      .collect()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn yaml_round_trip() {
+    let yaml = "architecture: x86_64\ncreation_date: 1661241600\nproperties:\n  os: ubuntu\n  release: jammy\ntemplates:\n  /etc/hostname:\n    when:\n    - create\n    - copy\n    template: hostname.tpl\n";
+
+    let metadata = ImageMetadata::from_yaml(yaml).unwrap();
+
+    assert_eq!(metadata.architecture, "x86_64");
+    assert_eq!(metadata.property("release"), Some("jammy"));
+    assert_eq!(metadata.templates["/etc/hostname"].template, "hostname.tpl");
+    assert!(!metadata.templates["/etc/hostname"].create_only);
+    assert_eq!(metadata.templates_on(TemplateTrigger::Copy), vec!["/etc/hostname"]);
+    assert!(metadata.templates_on(TemplateTrigger::Start).is_empty());
+    assert_eq!(ImageMetadata::from_yaml(&metadata.to_yaml().unwrap()).unwrap(), metadata);
+  }
+
+  #[test]
+  fn unknown_trigger() {
+    let yaml = "architecture: x86_64\ncreation_date: 0\ntemplates:\n  /etc/hosts:\n    when: [rename, reboot]\n    template: hosts.tpl\n";
+
+    let metadata = ImageMetadata::from_yaml(yaml).unwrap();
+
+    assert_eq!(metadata.templates["/etc/hosts"].when, vec![TemplateTrigger::Rename, TemplateTrigger::Other("reboot".to_string())]);
+    assert_eq!(metadata.templates_on(TemplateTrigger::Other("reboot".to_string())), vec!["/etc/hosts"]);
+    assert_eq!(ImageMetadata::from_yaml(&metadata.to_yaml().unwrap()).unwrap(), metadata);
+  }
+}
@@ -0,0 +1,204 @@
+//! Typed image properties, per-image update settings and their expiry report
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde_yaml::Value;
+
+use crate::api::image::LxcImage;
+use crate::api::time::{format_rfc3339, parse_rfc3339};
+use crate::error::LxcError;
+
+/// Well-known image properties
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageProperty {
+  Os,
+  Release,
+  /// Architecture in distribution naming, e.g. `amd64`
+  Architecture,
+  Variant,
+  Description,
+  /// Build serial, e.g. `20220823_07:43`
+  Serial,
+}
+
+impl ImageProperty {
+  pub const ALL: [ImageProperty; 6] = [
+    ImageProperty::Os,
+    ImageProperty::Release,
+    ImageProperty::Architecture,
+    ImageProperty::Variant,
+    ImageProperty::Description,
+    ImageProperty::Serial,
+  ];
+
+  /// Name of the property as LXD stores it
+  pub fn name(&self) -> &'static str {
+    match self {
+      ImageProperty::Os => "os",
+      ImageProperty::Release => "release",
+      ImageProperty::Architecture => "architecture",
+      ImageProperty::Variant => "variant",
+      ImageProperty::Description => "description",
+      ImageProperty::Serial => "serial",
+    }
+  }
+}
+
+impl fmt::Display for ImageProperty {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
+
+impl FromStr for ImageProperty {
+  type Err = LxcError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    ImageProperty::ALL.iter()
+      .find(|property| property.name() == s)
+      .copied()
+      .ok_or_else(|| LxcError::Invalid(format!("Unknown image property {}", s)))
+  }
+}
+
+/// Per-image settings changed through `lxc image edit`, unset fields are left as they are
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ImageSettings {
+  pub auto_update: Option<bool>,
+  pub public: Option<bool>,
+  /// Unix timestamp
+  pub expires_at: Option<i64>,
+}
+
+impl ImageSettings {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn auto_update(mut self, auto_update: bool) -> Self {
+    self.auto_update = Some(auto_update);
+    self
+  }
+
+  pub fn public(mut self, public: bool) -> Self {
+    self.public = Some(public);
+    self
+  }
+
+  pub fn expires_at(mut self, timestamp: i64) -> Self {
+    self.expires_at = Some(timestamp);
+    self
+  }
+
+  /// Change the settings in YAML printed by `lxc image show`, other keys are kept
+  pub fn apply(&self, yaml: &str) -> Result<String, LxcError> {
+    let mut image: Value = serde_yaml::from_str(yaml)?;
+    let mapping = image.as_mapping_mut().ok_or_else(|| LxcError::Parse("Image YAML is not a mapping".to_string()))?;
+
+    if let Some(auto_update) = self.auto_update {
+      mapping.insert("auto_update".into(), auto_update.into());
+    }
+
+    if let Some(public) = self.public {
+      mapping.insert("public".into(), public.into());
+    }
+
+    if let Some(expires_at) = self.expires_at {
+      mapping.insert("expires_at".into(), format_rfc3339(expires_at).into());
+    }
+
+    Ok(serde_yaml::to_string(&image)?)
+  }
+}
+
+/// When a cached or auto-updating image changes without anyone asking
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImageLifecycle {
+  pub fingerprint: String,
+  pub description: String,
+  pub cached: bool,
+  pub auto_update: bool,
+  /// End of support announced by the image, unix timestamp
+  pub expires_at: Option<i64>,
+  /// Latest time the next auto update check runs, unix timestamp
+  pub next_refresh: Option<i64>,
+  /// Time the unused cached image is removed, unix timestamp
+  pub cache_expires_at: Option<i64>,
+}
+
+/// Report for cached and auto-updating images.
+///
+/// `auto_update_interval` is `images.auto_update_interval` in hours and `remote_cache_expiry`
+/// is `images.remote_cache_expiry` in days, zero disables either. Unused cache expiry counts
+/// from the last use, or the upload for never used images.
+pub fn image_lifecycle(images: &[LxcImage], auto_update_interval: u64, remote_cache_expiry: u64, now: i64) -> Vec<ImageLifecycle> {
+  let timestamp = |date: &str| parse_rfc3339(date).filter(|timestamp| *timestamp > 0);
+
+  images.iter()
+    .filter(|image| image.cached || image.auto_update)
+    .map(|image| {
+      let last_activity = match (timestamp(&image.last_used_at), timestamp(&image.uploaded_at)) {
+        (Some(used), Some(uploaded)) => used.max(uploaded),
+        (used, uploaded) => used.or(uploaded).unwrap_or(now),
+      };
+
+      ImageLifecycle {
+        fingerprint: image.fingerprint.to_string(),
+        description: image.description().to_string(),
+        cached: image.cached,
+        auto_update: image.auto_update,
+        expires_at: timestamp(&image.expires_at),
+        next_refresh: Some(now + auto_update_interval as i64 * 3600).filter(|_| image.auto_update && auto_update_interval > 0),
+        cache_expires_at: Some(last_activity + remote_cache_expiry as i64 * 86400).filter(|_| image.cached && remote_cache_expiry > 0),
+      }
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn property_names() {
+    assert_eq!("serial".parse::<ImageProperty>().unwrap(), ImageProperty::Serial);
+    assert_eq!(ImageProperty::Os.to_string(), "os");
+    assert!("colour".parse::<ImageProperty>().is_err());
+  }
+
+  #[test]
+  fn apply_settings() {
+    let yaml = "auto_update: false\nproperties:\n  os: Ubuntu\npublic: false\nexpires_at: 2027-04-21T00:00:00Z\nprofiles:\n- default\n";
+
+    let edited = ImageSettings::new().auto_update(true).expires_at(1661241600).apply(yaml).unwrap();
+    let edited: Value = serde_yaml::from_str(&edited).unwrap();
+
+    assert_eq!(edited["auto_update"], Value::Bool(true));
+    assert_eq!(edited["public"], Value::Bool(false));
+    assert_eq!(edited["expires_at"].as_str(), Some("2022-08-23T08:00:00Z"));
+    assert_eq!(edited["properties"]["os"].as_str(), Some("Ubuntu"));
+  }
+
+  #[test]
+  fn lifecycle_report() {
+    let now = parse_rfc3339("2022-08-25T00:00:00Z").unwrap();
+
+    let mut cached = LxcImage::new("aaaa".to_string());
+    cached.cached = true;
+    cached.auto_update = true;
+    cached.uploaded_at = "2022-08-20T00:00:00Z".to_string();
+    cached.last_used_at = "0001-01-01T00:00:00Z".to_string();
+    cached.expires_at = "2027-04-21T00:00:00Z".to_string();
+
+    let report = image_lifecycle(&[cached, LxcImage::new("bbbb".to_string())], 6, 10, now);
+
+    assert_eq!(report.len(), 1);
+    assert_eq!(report[0].next_refresh, Some(now + 6 * 3600));
+    assert_eq!(report[0].cache_expires_at, parse_rfc3339("2022-08-30T00:00:00Z"));
+    assert_eq!(report[0].expires_at, parse_rfc3339("2027-04-21T00:00:00Z"));
+
+    let disabled = image_lifecycle(&[LxcImage { cached: true, auto_update: true, ..Default::default() }], 0, 0, now);
+    assert_eq!((disabled[0].next_refresh, disabled[0].cache_expires_at), (None, None));
+  }
+}
@@ -0,0 +1,223 @@
+//! Options of `lxc publish`
+
+use std::fmt;
+use std::str::FromStr;
+
+use crate::api::time::format_rfc3339;
+use crate::error::LxcError;
+
+/// Compression of the published image, passed as `--compression`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PublishCompression {
+  None,
+  Gzip,
+  Bzip2,
+  Lzma,
+  Xz,
+  Zstd,
+  /// Split image with squashfs rootfs
+  Squashfs,
+}
+
+impl PublishCompression {
+  pub const ALL: [PublishCompression; 7] = [
+    PublishCompression::None,
+    PublishCompression::Gzip,
+    PublishCompression::Bzip2,
+    PublishCompression::Lzma,
+    PublishCompression::Xz,
+    PublishCompression::Zstd,
+    PublishCompression::Squashfs,
+  ];
+
+  pub fn name(&self) -> &'static str {
+    match self {
+      PublishCompression::None => "none",
+      PublishCompression::Gzip => "gzip",
+      PublishCompression::Bzip2 => "bzip2",
+      PublishCompression::Lzma => "lzma",
+      PublishCompression::Xz => "xz",
+      PublishCompression::Zstd => "zstd",
+      PublishCompression::Squashfs => "squashfs",
+    }
+  }
+}
+
+impl fmt::Display for PublishCompression {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
+
+impl FromStr for PublishCompression {
+  type Err = LxcError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    PublishCompression::ALL.iter()
+      .find(|compression| compression.name() == s)
+      .copied()
+      .ok_or_else(|| LxcError::Invalid(format!("Unknown compression {}", s)))
+  }
+}
+
+/// Image published from an instance or its snapshot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImagePublish {
+  pub remote: String,
+  pub instance: String,
+  pub snapshot: Option<String>,
+  /// Remote the image is stored on, the instance remote by default
+  pub target_remote: Option<String>,
+  pub aliases: Vec<String>,
+  pub public: bool,
+  /// Unix timestamp
+  pub expires_at: Option<i64>,
+  pub compression: Option<PublishCompression>,
+  /// Replace the image the aliases point to
+  pub reuse: bool,
+  pub properties: Vec<(String, String)>,
+  /// Stop running instance for the publish and start it again afterwards
+  pub consistent: bool,
+}
+
+impl ImagePublish {
+  pub fn new(remote: &str, instance: &str) -> Self {
+    Self {
+      remote: remote.to_string(),
+      instance: instance.to_string(),
+      snapshot: None,
+      target_remote: None,
+      aliases: Vec::new(),
+      public: false,
+      expires_at: None,
+      compression: None,
+      reuse: false,
+      properties: Vec::new(),
+      consistent: false,
+    }
+  }
+
+  /// Publish the snapshot instead of the instance itself
+  pub fn snapshot(mut self, snapshot: &str) -> Self {
+    self.snapshot = Some(snapshot.to_string());
+    self
+  }
+
+  pub fn target_remote(mut self, remote: &str) -> Self {
+    self.target_remote = Some(remote.to_string());
+    self
+  }
+
+  /// Alias of the new image, can be given several times
+  pub fn alias(mut self, alias: &str) -> Self {
+    self.aliases.push(alias.to_string());
+    self
+  }
+
+  pub fn public(mut self, public: bool) -> Self {
+    self.public = public;
+    self
+  }
+
+  pub fn expires_at(mut self, timestamp: i64) -> Self {
+    self.expires_at = Some(timestamp);
+    self
+  }
+
+  pub fn compression(mut self, compression: PublishCompression) -> Self {
+    self.compression = Some(compression);
+    self
+  }
+
+  pub fn reuse(mut self, reuse: bool) -> Self {
+    self.reuse = reuse;
+    self
+  }
+
+  /// Image property such as `os` or `description`
+  pub fn property(mut self, key: &str, value: &str) -> Self {
+    self.properties.push((key.to_string(), value.to_string()));
+    self
+  }
+
+  /// Stop the running instance while it is published, snapshots are consistent anyway
+  pub fn consistent(mut self, consistent: bool) -> Self {
+    self.consistent = consistent;
+    self
+  }
+
+  /// Arguments of `lxc`
+  pub fn to_args(&self) -> Vec<String> {
+    let source = match &self.snapshot {
+      Some(snapshot) => format!("{}:{}/{}", self.remote, self.instance, snapshot),
+      None => format!("{}:{}", self.remote, self.instance),
+    };
+
+    let mut args = vec!["publish".to_string(), source, format!("{}:", self.target_remote.as_deref().unwrap_or(&self.remote))];
+
+    for alias in &self.aliases {
+      args.extend(["--alias".to_string(), alias.to_string()]);
+    }
+
+    if self.public {
+      args.push("--public".to_string());
+    }
+
+    if self.reuse {
+      args.push("--reuse".to_string());
+    }
+
+    if let Some(expires_at) = self.expires_at {
+      args.extend(["--expire".to_string(), format_rfc3339(expires_at)]);
+    }
+
+    if let Some(compression) = self.compression {
+      args.extend(["--compression".to_string(), compression.to_string()]);
+    }
+
+    args.extend(self.properties.iter().map(|(key, value)| format!("{}={}", key, value)));
+
+    args
+  }
+}
+
+/// Fingerprint from `Instance published with fingerprint: <fingerprint>`
+pub fn parse_published_fingerprint(output: &str) -> Option<String> {
+  output.lines()
+    .find_map(|line| line.split_once("fingerprint:"))
+    .map(|(_, fingerprint)| fingerprint.trim().to_string())
+    .filter(|fingerprint| !fingerprint.is_empty())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn publish_args() {
+    assert_eq!(ImagePublish::new("local", "web").to_args(), vec!["publish", "local:web", "local:"]);
+
+    let publish = ImagePublish::new("local", "web")
+      .snapshot("before-upgrade")
+      .target_remote("site-b")
+      .alias("web-golden")
+      .public(true)
+      .reuse(true)
+      .expires_at(1661241600)
+      .compression("zstd".parse().unwrap())
+      .property("os", "Ubuntu")
+      .property("description", "Web golden image");
+
+    assert_eq!(
+      publish.to_args().join(" "),
+      "publish local:web/before-upgrade site-b: --alias web-golden --public --reuse --expire 2022-08-23T08:00:00Z --compression zstd os=Ubuntu description=Web golden image"
+    );
+    assert!("brotli".parse::<PublishCompression>().is_err());
+  }
+
+  #[test]
+  fn published_fingerprint() {
+    assert_eq!(parse_published_fingerprint("Instance published with fingerprint: 8d1b7f2e33a5\n"), Some("8d1b7f2e33a5".to_string()));
+    assert_eq!(parse_published_fingerprint("Publishing instance: Image pack: 12% (3.00MB/s)\n"), None);
+  }
+}
@@ -0,0 +1,399 @@
+//! Private simplestreams image server built from a directory of exported images.
+//!
+//! The directory holds tarballs written by `lxc image export`: unified `<fingerprint>.tar.gz`
+//! images or split `meta-<fingerprint>.tar.xz` with `<fingerprint>.squashfs` (containers) or
+//! `<fingerprint>.qcow2` (virtual machines). `write_streams` generates `streams/v1` for them and
+//! `StreamsServer` serves the directory over HTTP.
+//!
+//! LXD only accepts `https://` simplestreams remotes, so put the server behind a TLS proxy.
+
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::{Component, Path, PathBuf};
+use std::thread;
+
+use crate::api::fingerprint::{image_fingerprint, sha256_file};
+use crate::api::image::ImageType;
+use crate::api::image_metadata::ImageMetadata;
+use crate::api::simplestreams::{simplestreams_architecture, IndexEntry, Product, ProductItem, ProductVersion, ProductsCatalog, SimpleStreams, StreamsIndex, StreamsSource, INDEX_PATH};
+use crate::api::time::{format_rfc2822, format_timestamp, now};
+use crate::error::LxcError;
+
+pub const IMAGES_PATH: &str = "streams/v1/images.json";
+
+/// File of an exported image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExportedFile {
+  /// Path relative to the served directory
+  pub path: String,
+  pub sha256: String,
+  pub size: u64,
+}
+
+/// Image found in the export directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportedImage {
+  pub fingerprint: String,
+  pub tp: ImageType,
+  pub metadata: ImageMetadata,
+  /// Metadata tarball of split images or the whole unified image
+  pub metadata_file: ExportedFile,
+  pub rootfs_file: Option<ExportedFile>,
+}
+
+impl ExportedImage {
+  /// Architecture in simplestreams naming, e.g. `amd64`
+  pub fn arch(&self) -> String {
+    if let Some(arch) = self.metadata.property("architecture") {
+      return arch.to_string();
+    }
+
+    simplestreams_architecture(&self.metadata.architecture).to_string()
+  }
+
+  /// Product name, e.g. `ubuntu:jammy:amd64:default`
+  pub fn product_name(&self) -> String {
+    let (os, release, variant) = self.product_parts();
+
+    format!("{}:{}:{}:{}", os, release, self.arch(), variant)
+  }
+
+  fn product_parts(&self) -> (String, String, String) {
+    let property = |key: &str, default: &str| self.metadata.property(key).unwrap_or(default).to_lowercase();
+
+    (property("os", "custom"), property("release", &self.fingerprint[..12.min(self.fingerprint.len())]), property("variant", "default"))
+  }
+
+  /// Version name of the image: `serial` property or creation date
+  pub fn version(&self) -> String {
+    match self.metadata.property("serial") {
+      Some(serial) => serial.to_string(),
+      None => {
+        let (date, time) = format_timestamp(self.metadata.creation_date);
+
+        format!("{}_{}", date.replace('-', ""), &time[..5])
+      },
+    }
+  }
+}
+
+fn exported_file(dir: &Path, name: &str) -> Result<ExportedFile, LxcError> {
+  let path = dir.join(name);
+
+  Ok(ExportedFile { path: name.to_string(), sha256: sha256_file(&path)?, size: fs::metadata(&path)?.len() })
+}
+
+fn file_id(name: &str) -> &str {
+  let name = name.strip_prefix("meta-").unwrap_or(name);
+
+  name.split('.').next().unwrap_or(name)
+}
+
+fn is_tarball(name: &str) -> bool {
+  [".tar", ".tar.gz", ".tar.xz", ".tar.bz2", ".tar.zst"].iter().any(|ext| name.ends_with(ext))
+}
+
+/// Find exported images in the directory and compute their fingerprints
+pub fn scan_exported_images(dir: &Path) -> Result<Vec<ExportedImage>, LxcError> {
+  let mut names = Vec::new();
+
+  for entry in fs::read_dir(dir)? {
+    let entry = entry?;
+
+    if entry.file_type()?.is_file() {
+      names.push(entry.file_name().to_string_lossy().to_string());
+    }
+  }
+
+  names.sort();
+
+  let metas: Vec<&String> = names.iter().filter(|name| name.starts_with("meta-")).collect();
+  let mut images = Vec::new();
+
+  for meta in &metas {
+    let rootfs = names.iter()
+      .find(|name| !name.starts_with("meta-") && file_id(name) == file_id(meta))
+      .ok_or_else(|| LxcError::Invalid(format!("Metadata tarball {} has no rootfs next to it", meta)))?;
+
+    let tp = if rootfs.ends_with(".qcow2") || rootfs.ends_with(".img") { ImageType::VirtualMachine } else { ImageType::Container };
+
+    images.push(ExportedImage {
+      fingerprint: image_fingerprint(&dir.join(meta), Some(&dir.join(rootfs)))?,
+      tp,
+      metadata: ImageMetadata::from_tarball(&dir.join(meta))?,
+      metadata_file: exported_file(dir, meta)?,
+      rootfs_file: Some(exported_file(dir, rootfs)?),
+    });
+  }
+
+  for name in &names {
+    let is_split_part = metas.iter().any(|meta| file_id(meta) == file_id(name));
+
+    if is_split_part || !is_tarball(name) {
+      continue;
+    }
+
+    let file = exported_file(dir, name)?;
+
+    images.push(ExportedImage {
+      fingerprint: file.sha256.to_string(),
+      tp: ImageType::Container,
+      metadata: ImageMetadata::from_tarball(&dir.join(name))?,
+      metadata_file: file,
+      rootfs_file: None,
+    });
+  }
+
+  Ok(images)
+}
+
+/// Build simplestreams index and products of the exported images
+pub fn build_streams(dir: &Path) -> Result<SimpleStreams, LxcError> {
+  let updated = format_rfc2822(now());
+  let mut products: BTreeMap<String, Product> = BTreeMap::new();
+
+  for image in scan_exported_images(dir)? {
+    let (os, release, variant) = image.product_parts();
+
+    let product = products.entry(image.product_name()).or_insert_with(|| Product {
+      aliases: format!("{os}/{release}/{variant},{os}/{release}"),
+      arch: image.arch(),
+      os: image.metadata.property("os").unwrap_or(&os).to_string(),
+      release: release.to_string(),
+      release_title: image.metadata.property("release").unwrap_or(&release).to_string(),
+      variant: variant.to_string(),
+      versions: BTreeMap::new(),
+    });
+
+    let mut version = image.version();
+
+    if product.versions.contains_key(&version) {
+      version = format!("{}_{}", version, &image.fingerprint[..8]);
+    }
+
+    product.versions.insert(version, image_version(&image));
+  }
+
+  let index = StreamsIndex {
+    format: "index:1.0".to_string(),
+    updated: updated.to_string(),
+    index: BTreeMap::from([("images".to_string(), IndexEntry {
+      datatype: "image-downloads".to_string(),
+      path: IMAGES_PATH.to_string(),
+      format: "products:1.0".to_string(),
+      updated: updated.to_string(),
+      products: products.keys().cloned().collect(),
+    })]),
+  };
+
+  let catalog = ProductsCatalog {
+    content_id: "images".to_string(),
+    datatype: "image-downloads".to_string(),
+    format: "products:1.0".to_string(),
+    updated,
+    products,
+  };
+
+  Ok(SimpleStreams { source: StreamsSource::Directory(dir.to_path_buf()), index, catalog })
+}
+
+fn image_version(image: &ExportedImage) -> ProductVersion {
+  let item = |file: &ExportedFile, ftype: &str| ProductItem {
+    ftype: ftype.to_string(),
+    path: file.path.to_string(),
+    sha256: file.sha256.to_string(),
+    size: file.size,
+    ..Default::default()
+  };
+
+  let rootfs = match &image.rootfs_file {
+    Some(rootfs) => rootfs,
+    None => return ProductVersion { items: BTreeMap::from([("lxd_combined.tar.gz".to_string(), item(&image.metadata_file, "lxd_combined.tar.gz"))]) },
+  };
+
+  let mut metadata = item(&image.metadata_file, "lxd.tar.xz");
+  let fingerprint = Some(image.fingerprint.to_string());
+
+  let (key, ftype) = if image.tp == ImageType::VirtualMachine {
+    metadata.combined_disk_kvm_img_sha256 = fingerprint;
+    ("disk.qcow2", "disk-kvm.img")
+  } else if rootfs.path.ends_with(".squashfs") {
+    metadata.combined_squashfs_sha256 = fingerprint;
+    ("root.squashfs", "squashfs")
+  } else {
+    metadata.combined_rootxz_sha256 = fingerprint;
+    ("root.tar.xz", "root.tar.xz")
+  };
+
+  ProductVersion { items: BTreeMap::from([("lxd.tar.xz".to_string(), metadata), (key.to_string(), item(rootfs, ftype))]) }
+}
+
+/// Generate `streams/v1/index.json` and `images.json` in the export directory
+pub fn write_streams(dir: &Path) -> Result<SimpleStreams, LxcError> {
+  let streams = build_streams(dir)?;
+
+  fs::create_dir_all(dir.join("streams/v1"))?;
+  fs::write(dir.join(INDEX_PATH), serde_json::to_string_pretty(&streams.index)?)?;
+  fs::write(dir.join(IMAGES_PATH), serde_json::to_string_pretty(&streams.catalog)?)?;
+
+  Ok(streams)
+}
+
+/// Small HTTP server for a simplestreams tree
+pub struct StreamsServer {
+  root: PathBuf,
+  listener: TcpListener,
+}
+
+impl StreamsServer {
+  pub fn bind(root: &Path, address: &str) -> Result<Self, LxcError> {
+    Ok(Self { root: root.to_path_buf(), listener: TcpListener::bind(address)? })
+  }
+
+  pub fn local_addr(&self) -> Result<SocketAddr, LxcError> {
+    Ok(self.listener.local_addr()?)
+  }
+
+  /// Serve requests until the listener fails, every connection is handled in its own thread
+  pub fn serve(self) -> Result<(), LxcError> {
+    for stream in self.listener.incoming() {
+      let stream = stream?;
+      let root = self.root.clone();
+
+      thread::spawn(move || {
+        let _ = handle_connection(&root, stream);
+      });
+    }
+
+    Ok(())
+  }
+}
+
+fn handle_connection(root: &Path, mut stream: TcpStream) -> io::Result<()> {
+  let mut request_line = String::new();
+  let mut reader = BufReader::new(stream.try_clone()?);
+
+  reader.read_line(&mut request_line)?;
+
+  // Skip the headers, nothing in them changes the response
+  let mut header = String::new();
+  while reader.read_line(&mut header)? > 2 {
+    header.clear();
+  }
+
+  let mut parts = request_line.split_whitespace();
+  let (method, target) = (parts.next().unwrap_or_default(), parts.next().unwrap_or_default());
+
+  if method != "GET" && method != "HEAD" {
+    return write_status(&mut stream, "405 Method Not Allowed");
+  }
+
+  let file = resolve_request_path(root, target).and_then(|path| File::open(path).ok().filter(|file| file.metadata().map(|m| m.is_file()).unwrap_or(false)));
+
+  let mut file = match file {
+    Some(file) => file,
+    None => return write_status(&mut stream, "404 Not Found"),
+  };
+
+  let content_type = if target.ends_with(".json") { "application/json" } else { "application/octet-stream" };
+
+  write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n", content_type, file.metadata()?.len())?;
+
+  if method == "GET" {
+    io::copy(&mut file, &mut stream)?;
+  }
+
+  stream.flush()
+}
+
+fn write_status(stream: &mut TcpStream, status: &str) -> io::Result<()> {
+  write!(stream, "HTTP/1.1 {}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n", status)
+}
+
+/// Map request target to a file under the root, paths escaping the root are rejected
+pub fn resolve_request_path(root: &Path, target: &str) -> Option<PathBuf> {
+  let path = Path::new(target.split('?').next()?.trim_start_matches('/'));
+
+  if path.components().all(|component| matches!(component, Component::Normal(_))) {
+    Some(root.join(path))
+  } else {
+    None
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::api::test_dir::TestDir;
+  use std::io::Read;
+  use std::process::Command;
+
+  fn export_dir(name: &str) -> TestDir {
+    let dir = TestDir::new(name);
+    fs::create_dir_all(dir.join("build")).unwrap();
+
+    fs::write(dir.join("build/metadata.yaml"), "architecture: x86_64\ncreation_date: 1661241600\nproperties:\n  os: Ubuntu\n  release: jammy\n  description: Golden jammy\n").unwrap();
+
+    let tar = |name: &str| assert!(Command::new("tar").args(["-czf", dir.join(name).to_str().unwrap(), "-C", dir.join("build").to_str().unwrap(), "metadata.yaml"]).status().unwrap().success());
+
+    tar("meta-split.tar.gz");
+    fs::write(dir.join("split.squashfs"), b"rootfs").unwrap();
+    tar("unified.tar.gz");
+    fs::remove_dir_all(dir.join("build")).unwrap();
+
+    dir
+  }
+
+  #[test]
+  fn build_tree_from_exports() {
+    let dir = export_dir("streams");
+
+    let images = scan_exported_images(&dir).unwrap();
+    assert_eq!(images.len(), 2);
+
+    let split = images.iter().find(|image| image.rootfs_file.is_some()).unwrap();
+    assert_eq!(split.fingerprint, image_fingerprint(&dir.join("meta-split.tar.gz"), Some(&dir.join("split.squashfs"))).unwrap());
+    assert_eq!(split.rootfs_file.as_ref().unwrap().size, 6);
+    assert_eq!(split.product_name(), "ubuntu:jammy:amd64:default");
+    assert_eq!(split.version(), "20220823_08:00");
+
+    write_streams(&dir).unwrap();
+
+    let streams = SimpleStreams::load(StreamsSource::Directory(dir.to_path_buf())).unwrap();
+    let product = &streams.catalog.products["ubuntu:jammy:amd64:default"];
+    assert_eq!(product.versions.len(), 2);
+    assert!(streams.resolve("ubuntu/jammy/amd64", ImageType::Container).is_some());
+
+    let fingerprints = streams.fingerprints();
+    assert!(images.iter().all(|image| fingerprints.contains_key(&image.fingerprint)));
+  }
+
+  #[test]
+  fn serve_tree() {
+    let dir = export_dir("serve");
+    write_streams(&dir).unwrap();
+
+    let server = StreamsServer::bind(&dir, "127.0.0.1:0").unwrap();
+    let address = server.local_addr().unwrap();
+    thread::spawn(move || server.serve());
+
+    let get = |path: &str| {
+      let mut stream = TcpStream::connect(address).unwrap();
+      write!(stream, "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).unwrap();
+
+      let mut response = String::new();
+      stream.read_to_string(&mut response).unwrap();
+      response
+    };
+
+    let index = get("/streams/v1/index.json");
+    assert!(index.starts_with("HTTP/1.1 200 OK"));
+    assert!(index.contains("image-downloads"));
+
+    assert!(get("/split.squashfs").ends_with("rootfs"));
+    assert!(get("/../etc/passwd").starts_with("HTTP/1.1 404"));
+    assert!(get("/missing.json").starts_with("HTTP/1.1 404"));
+  }
+}
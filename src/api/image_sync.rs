@@ -0,0 +1,128 @@
+//! Mirroring a set of images from one remote to others
+
+use std::collections::HashSet;
+
+use crate::api::image::{resolve_image, ImageFilter, LxcImage};
+use crate::error::LxcError;
+
+/// Images of the source remote which are mirrored
+#[derive(Debug, Clone, PartialEq)]
+pub enum SyncSelector {
+  /// Image with exactly this alias
+  Alias(String),
+  /// Image with the fingerprint or a fingerprint prefix only it has
+  Fingerprint(String),
+  /// All images matching the filter
+  Filter(ImageFilter),
+}
+
+impl SyncSelector {
+  /// Alias and filter selectors, a fingerprint is resolved against the whole set by `select_images`
+  pub fn matches(&self, image: &LxcImage) -> bool {
+    match self {
+      SyncSelector::Alias(name) => image.aliases.iter().any(|alias| alias.name == *name),
+      SyncSelector::Fingerprint(_) => false,
+      SyncSelector::Filter(filter) => filter.matches(image),
+    }
+  }
+}
+
+/// Differences between the source set and one destination remote
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SyncPlan {
+  pub destination: String,
+  /// Source images the destination does not have
+  pub copy: Vec<LxcImage>,
+  /// Destination images matching the selectors which are not in the source set anymore
+  pub remove: Vec<LxcImage>,
+}
+
+impl SyncPlan {
+  pub fn is_empty(&self) -> bool {
+    self.copy.is_empty() && self.remove.is_empty()
+  }
+}
+
+/// Source images matched by any of the selectors, an unknown or ambiguous fingerprint prefix is an error
+pub fn select_images<'a>(images: &'a [LxcImage], selectors: &[SyncSelector]) -> Result<Vec<&'a LxcImage>, LxcError> {
+  let mut fingerprints = HashSet::new();
+
+  for selector in selectors {
+    if let SyncSelector::Fingerprint(prefix) = selector {
+      fingerprints.insert(resolve_image(images, prefix)?.fingerprint.as_str());
+    }
+  }
+
+  Ok(images.iter().filter(|image| fingerprints.contains(image.fingerprint.as_str()) || selectors.iter().any(|selector| selector.matches(image))).collect())
+}
+
+/// Compare source and destination images by fingerprint.
+///
+/// A destination image whose alias now points to another fingerprint on the source is out of date,
+/// the new image gets copied and, with `prune`, the old one removed. Fingerprint selectors never remove anything,
+/// their image is always part of the source set.
+pub fn plan_sync(destination: &str, source: &[LxcImage], target: &[LxcImage], selectors: &[SyncSelector], prune: bool) -> Result<SyncPlan, LxcError> {
+  let selected = select_images(source, selectors)?;
+  let wanted: HashSet<&str> = selected.iter().map(|image| image.fingerprint.as_str()).collect();
+  let present: HashSet<&str> = target.iter().map(|image| image.fingerprint.as_str()).collect();
+
+  let copy = selected.iter()
+    .filter(|image| !present.contains(image.fingerprint.as_str()))
+    .map(|image| (*image).clone())
+    .collect();
+
+  let remove = if prune {
+    target.iter()
+      .filter(|image| selectors.iter().any(|selector| selector.matches(image)))
+      .filter(|image| !wanted.contains(image.fingerprint.as_str()))
+      .cloned()
+      .collect()
+  } else {
+    Vec::new()
+  };
+
+  Ok(SyncPlan { destination: destination.to_string(), copy, remove })
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn image(fingerprint: &str, alias: &str, os: &str) -> LxcImage {
+    let mut image = LxcImage::new(fingerprint.to_string()).alias(alias.to_string());
+    image.properties.insert("os".to_string(), os.to_string());
+    image
+  }
+
+  #[test]
+  fn plan_differences() {
+    let source = vec![image("aaaa", "jammy", "ubuntu"), image("bbbb", "bookworm", "debian"), image("cccc", "edge", "alpine")];
+    let target = vec![image("aaaa", "jammy", "ubuntu"), image("0bbb", "bookworm", "debian"), image("dddd", "scratch", "custom")];
+    let selectors = vec![SyncSelector::Alias("jammy".to_string()), SyncSelector::Filter(ImageFilter::new().os("debian"))];
+
+    let plan = plan_sync("site-b", &source, &target, &selectors, true).unwrap();
+
+    assert_eq!(plan.destination, "site-b");
+    assert_eq!(plan.copy.iter().map(|image| image.fingerprint.as_str()).collect::<Vec<_>>(), vec!["bbbb"]);
+    assert_eq!(plan.remove.iter().map(|image| image.fingerprint.as_str()).collect::<Vec<_>>(), vec!["0bbb"]);
+
+    let plan = plan_sync("site-b", &source, &target, &selectors, false).unwrap();
+    assert!(plan.remove.is_empty());
+
+    assert!(plan_sync("site-b", &source, &source, &selectors, true).unwrap().is_empty());
+  }
+
+  #[test]
+  fn exact_selectors() {
+    let source = vec![image("aaaa1", "jammy", "ubuntu"), image("aaaa2", "focal", "ubuntu"), image("bbbb", "bookworm", "debian")];
+
+    assert!(select_images(&source, &[SyncSelector::Alias(String::new())]).unwrap().is_empty());
+    assert!(select_images(&source, &[SyncSelector::Alias("aaaa1".to_string())]).unwrap().is_empty());
+
+    let selected = select_images(&source, &[SyncSelector::Fingerprint("bb".to_string())]).unwrap();
+    assert_eq!(selected.iter().map(|image| image.fingerprint.as_str()).collect::<Vec<_>>(), vec!["bbbb"]);
+
+    assert!(matches!(select_images(&source, &[SyncSelector::Fingerprint("aaaa".to_string())]), Err(LxcError::AmbiguousImage { .. })));
+    assert!(matches!(select_images(&source, &[SyncSelector::Fingerprint("cc".to_string())]), Err(LxcError::ImageNotFound(_))));
+  }
+}
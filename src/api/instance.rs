@@ -0,0 +1,444 @@
+//! Instances as returned by `lxc list --format json` and how new ones are created
+
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+use crate::api::cluster::ClusterTarget;
+use crate::api::image::ImageType;
+use crate::error::LxcError;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct Instance {
+  pub name: String,
+  pub description: String,
+  /// `Running`, `Stopped`, `Frozen` or `Error`
+  pub status: String,
+  pub status_code: u16,
+  #[serde(rename = "type")]
+  pub tp: ImageType,
+  pub architecture: String,
+  pub ephemeral: bool,
+  pub stateful: bool,
+  pub profiles: Vec<String>,
+  /// Configuration of the instance itself, see `expanded_config` for the one with profiles applied
+  pub config: HashMap<String, String>,
+  pub devices: HashMap<String, HashMap<String, String>>,
+  pub expanded_config: HashMap<String, String>,
+  pub expanded_devices: HashMap<String, HashMap<String, String>>,
+  /// Cluster member the instance runs on
+  pub location: String,
+  pub project: String,
+  pub created_at: String,
+  pub last_used_at: String,
+}
+
+impl Instance {
+  pub fn is_running(&self) -> bool {
+    self.status == "Running"
+  }
+
+  /// Fingerprint of the image the instance was created from
+  pub fn base_image(&self) -> Option<&str> {
+    self.config.get("volatile.base_image").map(|fingerprint| fingerprint.as_str())
+  }
+}
+
+/// Runtime state as returned by `/1.0/instances/<name>/state`, with snapshots from `lxc list`
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct InstanceState {
+  pub status: String,
+  pub status_code: u16,
+  /// PID of init on the host, `0` if stopped
+  pub pid: i64,
+  pub processes: i64,
+  pub cpu: CpuState,
+  pub memory: MemoryState,
+  /// Disk usage by device name, e.g. `root`
+  #[serde(deserialize_with = "null_as_default")]
+  pub disk: HashMap<String, DiskState>,
+  /// NICs by name inside the instance, e.g. `eth0`
+  #[serde(deserialize_with = "null_as_default")]
+  pub network: HashMap<String, NetworkState>,
+  #[serde(deserialize_with = "null_as_default")]
+  pub snapshots: Vec<InstanceSnapshot>,
+}
+
+/// LXD sends `null` instead of empty maps and lists for stopped instances
+fn null_as_default<'de, D: Deserializer<'de>, T: Default + Deserialize<'de>>(deserializer: D) -> Result<T, D::Error> {
+  Ok(Option::deserialize(deserializer)?.unwrap_or_default())
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CpuState {
+  /// CPU time in nanoseconds
+  pub usage: i64,
+}
+
+/// Memory usage in bytes
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MemoryState {
+  pub usage: u64,
+  pub usage_peak: u64,
+  pub swap_usage: u64,
+  pub swap_usage_peak: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DiskState {
+  /// Usage in bytes
+  pub usage: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NetworkState {
+  #[serde(deserialize_with = "null_as_default")]
+  pub addresses: Vec<NetworkAddress>,
+  pub counters: NetworkCounters,
+  pub hwaddr: String,
+  /// Name of the host side interface
+  pub host_name: String,
+  pub mtu: u32,
+  /// `up` or `down`
+  pub state: String,
+  #[serde(rename = "type")]
+  pub tp: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NetworkAddress {
+  /// `inet` or `inet6`
+  pub family: String,
+  pub address: String,
+  pub netmask: String,
+  /// `global`, `link` or `local`
+  pub scope: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NetworkCounters {
+  pub bytes_received: u64,
+  pub bytes_sent: u64,
+  pub packets_received: u64,
+  pub packets_sent: u64,
+  pub errors_received: u64,
+  pub errors_sent: u64,
+  pub packets_dropped_inbound: u64,
+  pub packets_dropped_outbound: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct InstanceSnapshot {
+  pub name: String,
+  pub stateful: bool,
+  pub created_at: String,
+  pub expires_at: String,
+}
+
+impl InstanceState {
+  /// State of an entry of `lxc list --format json`, which carries state and snapshots side by side
+  pub fn from_list_entry(entry: &Value) -> Result<Self, LxcError> {
+    let mut state: InstanceState = null_as_default(&entry["state"])?;
+    state.snapshots = null_as_default(&entry["snapshots"])?;
+
+    Ok(state)
+  }
+
+  pub fn is_running(&self) -> bool {
+    self.status == "Running"
+  }
+
+  /// Global addresses of the family (`inet` or `inet6`) on all NICs but loopback
+  pub fn addresses(&self, family: &str) -> Vec<&str> {
+    let mut nics: Vec<(&String, &NetworkState)> = self.network.iter().filter(|(_, nic)| nic.tp != "loopback").collect();
+    nics.sort_by_key(|(name, _)| name.as_str());
+
+    nics.into_iter()
+      .flat_map(|(_, nic)| nic.addresses.iter())
+      .filter(|address| address.family == family && address.scope == "global")
+      .map(|address| address.address.as_str())
+      .collect()
+  }
+
+  /// First global IPv4 address, NICs in name order
+  pub fn ipv4(&self) -> Option<&str> {
+    self.addresses("inet").into_iter().next()
+  }
+
+  pub fn ipv6(&self) -> Option<&str> {
+    self.addresses("inet6").into_iter().next()
+  }
+}
+
+/// Device added to the instance after it is created
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceDevice {
+  pub name: String,
+  /// Device type such as `disk`, `nic` or `proxy`
+  pub tp: String,
+  pub properties: BTreeMap<String, String>,
+}
+
+impl InstanceDevice {
+  pub fn new(name: &str, tp: &str) -> Self {
+    Self { name: name.to_string(), tp: tp.to_string(), properties: BTreeMap::new() }
+  }
+
+  pub fn property(mut self, key: &str, value: &str) -> Self {
+    self.properties.insert(key.to_string(), value.to_string());
+    self
+  }
+}
+
+/// New instance, created with `lxc init` and started for launch
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InstanceSpec {
+  pub image_remote: String,
+  /// Alias or fingerprint on the image remote
+  pub image: String,
+  pub remote: String,
+  pub name: String,
+  pub tp: ImageType,
+  /// `None` keeps the default profile, empty list creates the instance without profiles
+  pub profiles: Option<Vec<String>>,
+  pub config: BTreeMap<String, String>,
+  pub devices: Vec<InstanceDevice>,
+  pub storage: Option<String>,
+  pub network: Option<String>,
+  pub ephemeral: bool,
+  pub target: Option<ClusterTarget>,
+  pub project: Option<String>,
+}
+
+impl InstanceSpec {
+  /// Instance `name` on the local server from `image` of the image remote, e.g. `images` and `ubuntu/jammy`
+  pub fn new(image_remote: &str, image: &str, name: &str) -> Self {
+    Self {
+      image_remote: image_remote.to_string(),
+      image: image.to_string(),
+      remote: "local".to_string(),
+      name: name.to_string(),
+      tp: ImageType::Container,
+      profiles: None,
+      config: BTreeMap::new(),
+      devices: Vec::new(),
+      storage: None,
+      network: None,
+      ephemeral: false,
+      target: None,
+      project: None,
+    }
+  }
+
+  /// Remote the instance is created on
+  pub fn remote(mut self, remote: &str) -> Self {
+    self.remote = remote.to_string();
+    self
+  }
+
+  pub fn vm(mut self, vm: bool) -> Self {
+    self.tp = if vm { ImageType::VirtualMachine } else { ImageType::Container };
+    self
+  }
+
+  /// Profile to apply instead of the default one, can be given several times
+  pub fn profile(mut self, profile: &str) -> Self {
+    self.profiles.get_or_insert_with(Vec::new).push(profile.to_string());
+    self
+  }
+
+  /// Create the instance without any profile
+  pub fn no_profiles(mut self) -> Self {
+    self.profiles = Some(Vec::new());
+    self
+  }
+
+  pub fn config(mut self, key: &str, value: &str) -> Self {
+    self.config.insert(key.to_string(), value.to_string());
+    self
+  }
+
+  pub fn device(mut self, device: InstanceDevice) -> Self {
+    self.devices.push(device);
+    self
+  }
+
+  /// Storage pool of the root disk
+  pub fn storage(mut self, pool: &str) -> Self {
+    self.storage = Some(pool.to_string());
+    self
+  }
+
+  pub fn network(mut self, network: &str) -> Self {
+    self.network = Some(network.to_string());
+    self
+  }
+
+  pub fn ephemeral(mut self, ephemeral: bool) -> Self {
+    self.ephemeral = ephemeral;
+    self
+  }
+
+  pub fn target(mut self, target: ClusterTarget) -> Self {
+    self.target = Some(target);
+    self
+  }
+
+  pub fn project(mut self, project: &str) -> Self {
+    self.project = Some(project.to_string());
+    self
+  }
+
+  /// `--project` flag if the spec has a project
+  pub fn project_args(&self) -> Vec<String> {
+    self.project.iter().flat_map(|project| ["--project".to_string(), project.to_string()]).collect()
+  }
+
+  /// Arguments of `lxc init`
+  pub fn init_args(&self) -> Vec<String> {
+    let mut args = vec!["init".to_string(), format!("{}:{}", self.image_remote, self.image), format!("{}:{}", self.remote, self.name)];
+
+    if self.tp == ImageType::VirtualMachine {
+      args.push("--vm".to_string());
+    }
+
+    match &self.profiles {
+      Some(profiles) if profiles.is_empty() => args.push("--no-profiles".to_string()),
+      Some(profiles) => args.extend(profiles.iter().flat_map(|profile| ["--profile".to_string(), profile.to_string()])),
+      None => (),
+    }
+
+    args.extend(self.config.iter().flat_map(|(key, value)| ["--config".to_string(), format!("{}={}", key, value)]));
+
+    for (flag, value) in [("--storage", &self.storage), ("--network", &self.network)] {
+      if let Some(value) = value {
+        args.extend([flag.to_string(), value.to_string()]);
+      }
+    }
+
+    if self.ephemeral {
+      args.push("--ephemeral".to_string());
+    }
+
+    args.extend(self.target.iter().flat_map(|target| target.to_args()));
+    args.extend(self.project_args());
+
+    args
+  }
+
+  /// Arguments of `lxc config device add` for every device
+  pub fn device_args(&self) -> Vec<Vec<String>> {
+    self.devices.iter()
+      .map(|device| {
+        let mut args = vec!["config".to_string(), "device".to_string(), "add".to_string(), format!("{}:{}", self.remote, self.name), device.name.to_string(), device.tp.to_string()];
+        args.extend(device.properties.iter().map(|(key, value)| format!("{}={}", key, value)));
+        args.extend(self.project_args());
+        args
+      })
+      .collect()
+  }
+
+  /// Arguments of `lxc delete` removing the instance again, e.g. when adding a device fails
+  pub fn delete_args(&self) -> Vec<String> {
+    let mut args = vec!["delete".to_string(), "--force".to_string(), format!("{}:{}", self.remote, self.name)];
+    args.extend(self.project_args());
+    args
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_instance() {
+    let instance: Instance = serde_json::from_str(r#"{
+      "name": "web", "status": "Running", "status_code": 103, "type": "container", "architecture": "x86_64",
+      "profiles": ["default"], "config": {"volatile.base_image": "fc1727a92249", "limits.cpu": "2"},
+      "devices": {}, "expanded_devices": {"root": {"type": "disk", "path": "/", "pool": "default"}},
+      "location": "none", "project": "default", "created_at": "2022-08-24T05:39:00Z"
+    }"#).unwrap();
+
+    assert!(instance.is_running());
+    assert_eq!(instance.base_image(), Some("fc1727a92249"));
+    assert_eq!(instance.expanded_devices["root"]["pool"], "default");
+  }
+
+  const WEB_LIST_ENTRY: &str = r#"{
+    "name": "web", "status": "Running",
+    "state": {
+      "status": "Running", "status_code": 103, "pid": 4242, "processes": 37,
+      "cpu": {"usage": 81234000000},
+      "memory": {"usage": 104857600, "usage_peak": 209715200, "swap_usage": 0, "swap_usage_peak": 0},
+      "disk": {"root": {"usage": 1073741824}},
+      "network": {
+        "lo": {"addresses": [{"family": "inet", "address": "127.0.0.1", "netmask": "8", "scope": "local"}], "type": "loopback", "state": "up"},
+        "eth0": {
+          "addresses": [
+            {"family": "inet", "address": "10.10.0.15", "netmask": "24", "scope": "global"},
+            {"family": "inet6", "address": "fd42::15", "netmask": "64", "scope": "global"},
+            {"family": "inet6", "address": "fe80::1", "netmask": "64", "scope": "link"}
+          ],
+          "counters": {"bytes_received": 5000, "bytes_sent": 7000, "packets_received": 50, "packets_sent": 70},
+          "hwaddr": "00:16:3e:aa:bb:cc", "host_name": "veth1234", "mtu": 1500, "state": "up", "type": "broadcast"
+        }
+      }
+    },
+    "snapshots": [{"name": "before-upgrade", "stateful": false, "created_at": "2022-08-24T05:39:00Z", "expires_at": "0001-01-01T00:00:00Z"}]
+  }"#;
+
+  #[test]
+  fn parse_state() {
+    let entry: Value = serde_json::from_str(WEB_LIST_ENTRY).unwrap();
+    let state = InstanceState::from_list_entry(&entry).unwrap();
+
+    assert!(state.is_running());
+    assert_eq!((state.pid, state.processes), (4242, 37));
+    assert_eq!(state.memory.usage_peak, 209715200);
+    assert_eq!(state.disk["root"].usage, 1073741824);
+    assert_eq!(state.network["eth0"].counters.bytes_sent, 7000);
+    assert_eq!(state.ipv4(), Some("10.10.0.15"));
+    assert_eq!(state.ipv6(), Some("fd42::15"));
+    assert_eq!(state.snapshots[0].name, "before-upgrade");
+
+    let stopped: InstanceState = serde_json::from_str(r#"{"status": "Stopped", "status_code": 102, "pid": 0, "disk": null, "network": null}"#).unwrap();
+    assert_eq!(stopped.ipv4(), None);
+  }
+
+  #[test]
+  fn spec_args() {
+    assert_eq!(InstanceSpec::new("images", "ubuntu/jammy", "web").init_args(), vec!["init", "images:ubuntu/jammy", "local:web"]);
+
+    let spec = InstanceSpec::new("local", "golden", "db")
+      .remote("site-b")
+      .vm(true)
+      .profile("default")
+      .profile("db")
+      .config("limits.memory", "4GiB")
+      .config("limits.cpu", "2")
+      .storage("fast")
+      .network("lxdbr1")
+      .ephemeral(true)
+      .target(ClusterTarget::Group("ssd".to_string()))
+      .project("staging")
+      .device(InstanceDevice::new("data", "disk").property("source", "/srv/db").property("path", "/var/lib/db"));
+
+    assert_eq!(
+      spec.init_args().join(" "),
+      "init local:golden site-b:db --vm --profile default --profile db --config limits.cpu=2 --config limits.memory=4GiB --storage fast --network lxdbr1 --ephemeral --target @ssd --project staging"
+    );
+    assert_eq!(spec.device_args()[0].join(" "), "config device add site-b:db data disk path=/var/lib/db source=/srv/db --project staging");
+    assert_eq!(spec.delete_args().join(" "), "delete --force site-b:db --project staging");
+    assert!(InstanceSpec::new("images", "alpine/edge", "tmp").no_profiles().init_args().contains(&"--no-profiles".to_string()));
+  }
+}
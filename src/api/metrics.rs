@@ -0,0 +1,263 @@
+//! Parser for `/1.0/metrics` in the Prometheus text format
+
+use std::collections::BTreeMap;
+use std::time::SystemTime;
+
+use crate::error::LxcError;
+
+/// Single sample line of the exposition
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricSample {
+  pub name: String,
+  pub labels: BTreeMap<String, String>,
+  pub value: f64,
+  pub timestamp: Option<i64>,
+}
+
+impl MetricSample {
+  pub fn label(&self, name: &str) -> Option<&str> {
+    self.labels.get(name).map(|value| value.as_str())
+  }
+}
+
+/// Counters and gauges of one instance, bytes and seconds are totals since instance start
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstanceMetrics {
+  pub project: String,
+  pub name: String,
+  /// `container` or `virtual-machine`
+  pub tp: String,
+  pub cpu_seconds: f64,
+  pub memory_usage_bytes: f64,
+  pub memory_total_bytes: f64,
+  pub disk_read_bytes: f64,
+  pub disk_written_bytes: f64,
+  pub network_receive_bytes: f64,
+  pub network_transmit_bytes: f64,
+  pub processes: f64,
+}
+
+/// Metrics of all instances collected at one moment
+#[derive(Debug, Clone, PartialEq)]
+pub struct MetricsScrape {
+  pub taken_at: SystemTime,
+  pub instances: Vec<InstanceMetrics>,
+}
+
+/// Per-second rates of an instance between two scrapes
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InstanceRates {
+  pub project: String,
+  pub name: String,
+  /// CPU time per second, `1.0` is one fully used core
+  pub cpu_usage: f64,
+  pub disk_read_bytes_per_sec: f64,
+  pub disk_written_bytes_per_sec: f64,
+  pub network_receive_bytes_per_sec: f64,
+  pub network_transmit_bytes_per_sec: f64,
+}
+
+/// Parse Prometheus text exposition into samples, comments are skipped
+pub fn parse_metrics(text: &str) -> Result<Vec<MetricSample>, LxcError> {
+  text.lines()
+    .map(|line| line.trim())
+    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+    .map(parse_sample)
+    .collect()
+}
+
+fn parse_sample(line: &str) -> Result<MetricSample, LxcError> {
+  let invalid = |reason: &str| LxcError::Parse(format!("{} in metric line {}", reason, line));
+
+  let name_end = line.find(|c: char| c == '{' || c.is_whitespace()).ok_or_else(|| invalid("Missing value"))?;
+  let name = line[..name_end].to_string();
+  let mut rest = &line[name_end..];
+  let mut labels = BTreeMap::new();
+
+  if let Some(body) = rest.strip_prefix('{') {
+    let (parsed, after) = parse_labels(body).ok_or_else(|| invalid("Malformed labels"))?;
+
+    labels = parsed;
+    rest = after;
+  }
+
+  let mut fields = rest.split_whitespace();
+  let value = fields.next().ok_or_else(|| invalid("Missing value")).and_then(|value| parse_value(value).ok_or_else(|| invalid("Invalid value")))?;
+  let timestamp = match fields.next() {
+    Some(ts) => Some(ts.parse::<i64>().map_err(|_| invalid("Invalid timestamp"))?),
+    None => None,
+  };
+
+  Ok(MetricSample { name, labels, value, timestamp })
+}
+
+/// Parse `key="value",...}` and return labels with the rest of the line
+fn parse_labels(body: &str) -> Option<(BTreeMap<String, String>, &str)> {
+  let mut labels = BTreeMap::new();
+  let mut rest = body.trim_start();
+
+  loop {
+    if let Some(after) = rest.strip_prefix('}') {
+      return Some((labels, after));
+    }
+
+    let (key, after_key) = rest.split_once('=')?;
+    let mut chars = after_key.strip_prefix('"')?.char_indices();
+    let mut value = String::new();
+
+    let end = loop {
+      match chars.next()? {
+        (_, '\\') => match chars.next()?.1 {
+          'n' => value.push('\n'),
+          other => value.push(other),
+        },
+        (i, '"') => break i,
+        (_, c) => value.push(c),
+      }
+    };
+
+    labels.insert(key.trim().to_string(), value);
+
+    rest = after_key[end + 2..].trim_start();
+    rest = rest.strip_prefix(',').unwrap_or(rest).trim_start();
+  }
+}
+
+fn parse_value(value: &str) -> Option<f64> {
+  match value {
+    "+Inf" => Some(f64::INFINITY),
+    "-Inf" => Some(f64::NEG_INFINITY),
+    _ => value.parse::<f64>().ok(),
+  }
+}
+
+/// Group LXD samples by instance
+pub fn instance_metrics(samples: &[MetricSample]) -> Vec<InstanceMetrics> {
+  let mut instances: BTreeMap<(String, String), InstanceMetrics> = BTreeMap::new();
+  let mut memory_available: BTreeMap<(String, String), f64> = BTreeMap::new();
+
+  for sample in samples {
+    let (Some(name), project) = (sample.label("name"), sample.label("project").unwrap_or("default")) else {
+      continue;
+    };
+
+    let id = (project.to_string(), name.to_string());
+    let instance = instances.entry(id.clone()).or_insert_with(|| InstanceMetrics {
+      project: project.to_string(),
+      name: name.to_string(),
+      tp: sample.label("type").unwrap_or_default().to_string(),
+      ..Default::default()
+    });
+
+    let is_loopback = sample.label("device") == Some("lo");
+
+    match sample.name.as_str() {
+      "lxd_cpu_seconds_total" if sample.label("mode") != Some("idle") => instance.cpu_seconds += sample.value,
+      "lxd_memory_MemTotal_bytes" => instance.memory_total_bytes = sample.value,
+      "lxd_memory_MemAvailable_bytes" => { memory_available.insert(id, sample.value); },
+      "lxd_disk_read_bytes_total" => instance.disk_read_bytes += sample.value,
+      "lxd_disk_written_bytes_total" => instance.disk_written_bytes += sample.value,
+      "lxd_network_receive_bytes_total" if !is_loopback => instance.network_receive_bytes += sample.value,
+      "lxd_network_transmit_bytes_total" if !is_loopback => instance.network_transmit_bytes += sample.value,
+      "lxd_procs_total" => instance.processes = sample.value,
+      _ => {},
+    }
+  }
+
+  for (id, available) in memory_available {
+    if let Some(instance) = instances.get_mut(&id) {
+      instance.memory_usage_bytes = (instance.memory_total_bytes - available).max(0.0);
+    }
+  }
+
+  instances.into_values().collect()
+}
+
+/// Compute per-second rates between two scrapes of the same daemon.
+///
+/// Instances missing in one of the scrapes are skipped, counter resets are treated as
+/// counters starting from zero.
+pub fn metrics_rates(previous: &MetricsScrape, current: &MetricsScrape) -> Vec<InstanceRates> {
+  let elapsed = match current.taken_at.duration_since(previous.taken_at) {
+    Ok(elapsed) if !elapsed.is_zero() => elapsed.as_secs_f64(),
+    _ => return Vec::new(),
+  };
+
+  let rate = |before: f64, after: f64| if after >= before { (after - before) / elapsed } else { after / elapsed };
+
+  current.instances.iter()
+    .filter_map(|now| {
+      let before = previous.instances.iter().find(|i| i.project == now.project && i.name == now.name)?;
+
+      Some(InstanceRates {
+        project: now.project.to_string(),
+        name: now.name.to_string(),
+        cpu_usage: rate(before.cpu_seconds, now.cpu_seconds),
+        disk_read_bytes_per_sec: rate(before.disk_read_bytes, now.disk_read_bytes),
+        disk_written_bytes_per_sec: rate(before.disk_written_bytes, now.disk_written_bytes),
+        network_receive_bytes_per_sec: rate(before.network_receive_bytes, now.network_receive_bytes),
+        network_transmit_bytes_per_sec: rate(before.network_transmit_bytes, now.network_transmit_bytes),
+      })
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::time::Duration;
+
+  const METRICS: &str = r#"# HELP lxd_cpu_seconds_total The total number of CPU time used in seconds.
+# TYPE lxd_cpu_seconds_total counter
+lxd_cpu_seconds_total{cpu="0",mode="system",name="web",project="default",type="container"} 10.5
+lxd_cpu_seconds_total{cpu="0",mode="user",name="web",project="default",type="container"} 20
+lxd_cpu_seconds_total{cpu="0",mode="idle",name="web",project="default",type="container"} 1000
+lxd_memory_MemTotal_bytes{name="web",project="default",type="container"} 1.073741824e+09
+lxd_memory_MemAvailable_bytes{name="web",project="default",type="container"} 536870912
+lxd_disk_read_bytes_total{device="sda",name="web",project="default",type="container"} 4096
+lxd_network_receive_bytes_total{device="eth0",name="web",project="default",type="container"} 1000
+lxd_network_receive_bytes_total{device="lo",name="web",project="default",type="container"} 999999
+lxd_procs_total{name="web",project="default",type="container"} 12
+lxd_warnings_total 3
+"#;
+
+  #[test]
+  fn parse_exposition() {
+    let samples = parse_metrics(METRICS).unwrap();
+
+    assert_eq!(samples.len(), 10);
+    assert_eq!(samples[0].label("mode"), Some("system"));
+    assert_eq!(samples[9].name, "lxd_warnings_total");
+    assert!(samples[9].labels.is_empty());
+
+    let escaped = parse_metrics("m{path=\"a\\\"b\",x=\"1\"} +Inf 1660000000000").unwrap();
+    assert_eq!(escaped[0].label("path"), Some("a\"b"));
+    assert_eq!(escaped[0].value, f64::INFINITY);
+    assert_eq!(escaped[0].timestamp, Some(1660000000000));
+
+    assert!(parse_metrics("broken{name=\"x\" 1").is_err());
+  }
+
+  #[test]
+  fn group_and_rates() {
+    let instances = instance_metrics(&parse_metrics(METRICS).unwrap());
+
+    assert_eq!(instances.len(), 1);
+    assert_eq!(instances[0].cpu_seconds, 30.5);
+    assert_eq!(instances[0].memory_usage_bytes, 536870912.0);
+    assert_eq!(instances[0].network_receive_bytes, 1000.0);
+    assert_eq!(instances[0].processes, 12.0);
+
+    let previous = MetricsScrape { taken_at: SystemTime::UNIX_EPOCH, instances: instances.clone() };
+    let mut later = instances[0].clone();
+    later.cpu_seconds += 20.0;
+    later.network_receive_bytes += 10000.0;
+    let current = MetricsScrape { taken_at: SystemTime::UNIX_EPOCH + Duration::from_secs(10), instances: vec![later] };
+
+    let rates = metrics_rates(&previous, &current);
+
+    assert_eq!(rates[0].cpu_usage, 2.0);
+    assert_eq!(rates[0].network_receive_bytes_per_sec, 1000.0);
+    assert_eq!(rates[0].disk_read_bytes_per_sec, 0.0);
+  }
+}
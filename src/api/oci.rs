@@ -0,0 +1,464 @@
+//! Convert OCI image layouts into LXD images.
+//!
+//! Layers are unpacked with `tar` in manifest order. Whiteouts of a layer remove files of the
+//! layers below before the layer itself is unpacked, see the OCI image spec `layer.md`. A layer
+//! whose entries lead through a symlink of a lower layer is refused, `tar` would follow it out of the rootfs.
+
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::api::fingerprint::sha256_file;
+use crate::api::image_builder::{BuiltImage, ImageBuilder};
+use crate::error::LxcError;
+use crate::template::{path_arg, template_output};
+
+const INDEX_MEDIA_TYPES: [&str; 2] = ["application/vnd.oci.image.index.v1+json", "application/vnd.docker.distribution.manifest.list.v2+json"];
+const REF_NAME: &str = "org.opencontainers.image.ref.name";
+const WHITEOUT_PREFIX: &str = ".wh.";
+const OPAQUE_WHITEOUT: &str = ".wh..wh..opq";
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct OciPlatform {
+  pub architecture: String,
+  pub os: String,
+  pub variant: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct OciDescriptor {
+  pub media_type: String,
+  /// `sha256:<hex>`
+  pub digest: String,
+  pub size: u64,
+  pub annotations: BTreeMap<String, String>,
+  pub platform: Option<OciPlatform>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct OciIndex {
+  pub manifests: Vec<OciDescriptor>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct OciManifest {
+  pub config: OciDescriptor,
+  pub layers: Vec<OciDescriptor>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, rename_all = "PascalCase")]
+pub struct OciRuntimeConfig {
+  pub env: Vec<String>,
+  pub entrypoint: Vec<String>,
+  pub cmd: Vec<String>,
+  pub working_dir: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct OciConfig {
+  /// Go architecture name, e.g. `amd64`
+  pub architecture: String,
+  pub os: String,
+  pub config: OciRuntimeConfig,
+}
+
+/// OCI image layout directory, e.g. written by `skopeo copy docker://... oci:<dir>:<tag>`
+#[derive(Debug, Clone, PartialEq)]
+pub struct OciLayout {
+  pub dir: PathBuf,
+  pub index: OciIndex,
+}
+
+impl OciLayout {
+  pub fn open(dir: &Path) -> Result<Self, LxcError> {
+    if !dir.join("oci-layout").is_file() {
+      return Err(LxcError::Invalid(format!("{} is not an OCI image layout", dir.display())));
+    }
+
+    let index = serde_json::from_str(&fs::read_to_string(dir.join("index.json"))?)?;
+
+    Ok(Self { dir: dir.to_path_buf(), index })
+  }
+
+  /// Path of the blob after checking that its content matches the digest
+  pub fn blob(&self, digest: &str) -> Result<PathBuf, LxcError> {
+    let hex = digest.strip_prefix("sha256:")
+      .filter(|hex| hex.len() == 64 && hex.chars().all(|c| c.is_ascii_hexdigit()))
+      .ok_or_else(|| LxcError::Invalid(format!("Unsupported blob digest {}", digest)))?;
+
+    let path = self.dir.join("blobs/sha256").join(hex);
+    let actual = sha256_file(&path)?;
+
+    if actual != hex {
+      return Err(LxcError::FingerprintMismatch { expected: hex.to_string(), actual });
+    }
+
+    Ok(path)
+  }
+
+  fn read_blob<T: serde::de::DeserializeOwned>(&self, digest: &str) -> Result<T, LxcError> {
+    Ok(serde_json::from_str(&fs::read_to_string(self.blob(digest)?)?)?)
+  }
+
+  /// Manifest tagged with `reference`, or the only one of the layout.
+  ///
+  /// Nested image indexes are resolved to the manifest for the host architecture.
+  pub fn manifest(&self, reference: Option<&str>) -> Result<OciManifest, LxcError> {
+    let descriptor = match reference {
+      Some(reference) => self.index.manifests.iter().find(|manifest| manifest.annotations.get(REF_NAME).map(|name| name == reference).unwrap_or(false)),
+      None if self.index.manifests.len() == 1 => self.index.manifests.first(),
+      None => return Err(LxcError::Invalid("OCI layout has several manifests, choose one by reference".to_string())),
+    }
+    .ok_or_else(|| LxcError::ImageNotFound(reference.unwrap_or_default().to_string()))?;
+
+    let mut descriptor = descriptor.clone();
+
+    while INDEX_MEDIA_TYPES.contains(&descriptor.media_type.as_str()) {
+      let index: OciIndex = self.read_blob(&descriptor.digest)?;
+      let host = go_architecture(std::env::consts::ARCH);
+
+      descriptor = index.manifests.iter()
+        .find(|manifest| manifest.platform.as_ref().map(|platform| platform.architecture == host && platform.os == "linux").unwrap_or(false))
+        .cloned()
+        .ok_or_else(|| LxcError::Invalid(format!("OCI image index has no linux/{} manifest", host)))?;
+    }
+
+    self.read_blob(&descriptor.digest)
+  }
+
+  pub fn config(&self, manifest: &OciManifest) -> Result<OciConfig, LxcError> {
+    self.read_blob(&manifest.config.digest)
+  }
+
+  /// Unpack layers of the manifest into an empty `rootfs` directory
+  pub fn unpack_rootfs(&self, manifest: &OciManifest, rootfs: &Path) -> Result<(), LxcError> {
+    fs::create_dir_all(rootfs)?;
+
+    for layer in &manifest.layers {
+      let blob = self.blob(&layer.digest)?;
+      let listing = template_output("tar", vec!["-tf".to_string(), path_arg(&blob)], "Failed to list OCI layer")?;
+      let entries: HashSet<PathBuf> = listing.lines().map(entry_path).collect();
+
+      for entry in listing.lines() {
+        check_entry(rootfs, entry, &entries)?;
+        apply_whiteout(rootfs, entry)?;
+      }
+
+      template_output("tar", vec!["-xf".to_string(), path_arg(&blob), "-C".to_string(), path_arg(rootfs), "--exclude=.wh.*".to_string()], "Failed to unpack OCI layer")?;
+    }
+
+    Ok(())
+  }
+}
+
+/// Go architecture name of a kernel architecture name
+fn go_architecture(architecture: &str) -> &str {
+  match architecture {
+    "x86_64" => "amd64",
+    "aarch64" => "arm64",
+    "x86" | "i686" => "386",
+    "arm" | "armv7l" => "arm",
+    "powerpc64" => "ppc64le",
+    other => other,
+  }
+}
+
+/// Kernel architecture name LXD uses for a Go architecture name
+fn kernel_architecture(architecture: &str) -> &str {
+  match architecture {
+    "amd64" => "x86_64",
+    "arm64" => "aarch64",
+    "386" => "i686",
+    "arm" => "armv7l",
+    other => other,
+  }
+}
+
+/// Entry of a layer listing without `.` components and trailing slash
+fn entry_path(entry: &str) -> PathBuf {
+  Path::new(entry).components().filter(|component| *component != Component::CurDir).collect()
+}
+
+/// Refuse layer entry which `tar` would unpack through a symlink left by the layers below.
+///
+/// A symlinked parent the layer replaces with its own entry is fine, `tar` removes the symlink first.
+/// Whiteouts are not unpacked, `apply_whiteout` never follows symlinks.
+fn check_entry(rootfs: &Path, entry: &str, layer_entries: &HashSet<PathBuf>) -> Result<(), LxcError> {
+  let path = Path::new(entry);
+
+  if path.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+    return Err(LxcError::Invalid(format!("OCI layer entry {} escapes the rootfs", entry)));
+  }
+
+  if path.file_name().map(|name| name.to_string_lossy().starts_with(WHITEOUT_PREFIX)).unwrap_or(false) {
+    return Ok(());
+  }
+
+  let parent = entry_path(entry).parent().map(Path::to_path_buf).unwrap_or_default();
+  let mut ancestor = PathBuf::new();
+
+  for component in parent.components() {
+    ancestor.push(component);
+
+    if layer_entries.contains(&ancestor) {
+      continue;
+    }
+
+    match fs::symlink_metadata(rootfs.join(&ancestor)) {
+      Ok(metadata) if metadata.file_type().is_symlink() => {
+        return Err(LxcError::Invalid(format!("OCI layer entry {} leads through symlink {} of a lower layer", entry, ancestor.display())));
+      },
+      Ok(metadata) if metadata.is_dir() => (),
+      // Created by the layer itself, or `tar` fails on a file in the way
+      _ => return Ok(()),
+    }
+  }
+
+  Ok(())
+}
+
+/// Remove what the layer entry whites out from the layers below it
+fn apply_whiteout(rootfs: &Path, entry: &str) -> Result<(), LxcError> {
+  let entry = Path::new(entry);
+  let name = match entry.file_name().and_then(|name| name.to_str()) {
+    Some(name) if name.starts_with(WHITEOUT_PREFIX) => name,
+    _ => return Ok(()),
+  };
+
+  if entry.components().any(|component| !matches!(component, Component::Normal(_) | Component::CurDir)) {
+    return Err(LxcError::Invalid(format!("OCI layer entry {} escapes the rootfs", entry.display())));
+  }
+
+  let parent = entry.parent().unwrap_or(Path::new(""));
+  let dir = rootfs.join(parent);
+
+  // Symlinks of lower layers may point outside of the rootfs, never follow them
+  let mut ancestor = rootfs.to_path_buf();
+
+  for component in parent.components() {
+    ancestor.push(component);
+
+    match fs::symlink_metadata(&ancestor) {
+      Ok(metadata) if metadata.is_dir() => (),
+      _ => return Ok(()),
+    }
+  }
+
+  if name == OPAQUE_WHITEOUT {
+    if dir.is_dir() {
+      for child in fs::read_dir(&dir)? {
+        remove_path(&child?.path())?;
+      }
+    }
+
+    return Ok(());
+  }
+
+  remove_path(&dir.join(&name[WHITEOUT_PREFIX.len()..]))
+}
+
+fn remove_path(path: &Path) -> Result<(), LxcError> {
+  match fs::symlink_metadata(path) {
+    Ok(metadata) if metadata.is_dir() => fs::remove_dir_all(path)?,
+    Ok(_) => fs::remove_file(path)?,
+    Err(_) => (),
+  }
+
+  Ok(())
+}
+
+/// Build a unified LXD image from the OCI image into `output`.
+///
+/// The rootfs is assembled under `output` and removed afterwards. Generated metadata takes the
+/// architecture from the image config, the layout directory name becomes `os` and `reference` the release.
+pub fn build_oci_image(layout: &Path, reference: Option<&str>, output: &Path) -> Result<BuiltImage, LxcError> {
+  let layout = OciLayout::open(layout)?;
+  let manifest = layout.manifest(reference)?;
+  let config = layout.config(&manifest)?;
+
+  let rootfs = output.join(format!(".oci-rootfs-{}", std::process::id()));
+  let _ = fs::remove_dir_all(&rootfs);
+
+  let result = layout.unpack_rootfs(&manifest, &rootfs).and_then(|_| {
+    let release = reference.unwrap_or("latest");
+    let name = layout.dir.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+
+    ImageBuilder::new(&rootfs)
+      .architecture(kernel_architecture(&config.architecture))
+      .property("os", &name)
+      .property("release", release)
+      .property("variant", "oci")
+      .property("description", &format!("{} {} ({}, OCI)", name, release, config.architecture))
+      .build(output)
+  });
+
+  let _ = fs::remove_dir_all(&rootfs);
+
+  result
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::api::image_builder::validate_image_tarballs;
+  use crate::api::test_dir::TestDir;
+
+  /// Store the file in the layout and return its descriptor
+  fn add_blob(layout: &Path, media_type: &str, file: &Path) -> serde_json::Value {
+    let digest = sha256_file(file).unwrap();
+    fs::copy(file, layout.join("blobs/sha256").join(&digest)).unwrap();
+
+    serde_json::json!({ "mediaType": media_type, "digest": format!("sha256:{}", digest), "size": fs::metadata(file).unwrap().len() })
+  }
+
+  fn layer(dir: &Path, name: &str, files: &[(&str, &str)], compress: bool) -> PathBuf {
+    let content = dir.join(name);
+    fs::create_dir_all(&content).unwrap();
+
+    for (path, data) in files {
+      fs::create_dir_all(content.join(path).parent().unwrap()).unwrap();
+      fs::write(content.join(path), data).unwrap();
+    }
+
+    let tarball = dir.join(format!("{}.tar", name));
+    let flag = if compress { "-czf" } else { "-cf" };
+    template_output("tar", vec![flag.to_string(), path_arg(&tarball), "-C".to_string(), path_arg(&content), ".".to_string()], "").unwrap();
+
+    tarball
+  }
+
+  /// Two layer layout: the upper layer deletes `etc/removed` and replaces the content of `opt/app`
+  fn fixture_layout(name: &str) -> TestDir {
+    let dir = TestDir::new(name);
+
+    let layout = dir.join("alpine-app");
+    fs::create_dir_all(layout.join("blobs/sha256")).unwrap();
+    fs::write(layout.join("oci-layout"), r#"{"imageLayoutVersion": "1.0.0"}"#).unwrap();
+
+    let base = layer(&dir, "base", &[("etc/os-release", "ID=alpine\n"), ("etc/removed", "x"), ("opt/app/old", "1"), ("opt/app/older", "2")], true);
+    let upper = layer(&dir, "upper", &[("etc/.wh.removed", ""), ("opt/app/.wh..wh..opq", ""), ("opt/app/new", "3")], false);
+
+    let config = dir.join("config.json");
+    fs::write(&config, r#"{"architecture": "arm64", "os": "linux", "config": {"Entrypoint": ["/app"], "Env": ["PATH=/bin"]}}"#).unwrap();
+
+    let manifest = serde_json::json!({
+      "schemaVersion": 2,
+      "config": add_blob(&layout, "application/vnd.oci.image.config.v1+json", &config),
+      "layers": [
+        add_blob(&layout, "application/vnd.oci.image.layer.v1.tar+gzip", &base),
+        add_blob(&layout, "application/vnd.oci.image.layer.v1.tar", &upper),
+      ],
+    });
+
+    let manifest_file = dir.join("manifest.json");
+    fs::write(&manifest_file, manifest.to_string()).unwrap();
+
+    let mut descriptor = add_blob(&layout, "application/vnd.oci.image.manifest.v1+json", &manifest_file);
+    descriptor["annotations"] = serde_json::json!({ REF_NAME: "3.16" });
+    fs::write(layout.join("index.json"), serde_json::json!({ "schemaVersion": 2, "manifests": [descriptor] }).to_string()).unwrap();
+
+    dir
+  }
+
+  #[test]
+  fn unpack_layers_with_whiteouts() {
+    let dir = fixture_layout("oci-unpack");
+    let layout = OciLayout::open(&dir.join("alpine-app")).unwrap();
+    let manifest = layout.manifest(Some("3.16")).unwrap();
+    let rootfs = dir.join("rootfs");
+
+    assert_eq!(layout.config(&manifest).unwrap().config.entrypoint, vec!["/app"]);
+    assert!(layout.manifest(Some("edge")).is_err());
+
+    layout.unpack_rootfs(&manifest, &rootfs).unwrap();
+
+    assert_eq!(fs::read_to_string(rootfs.join("etc/os-release")).unwrap(), "ID=alpine\n");
+    assert!(!rootfs.join("etc/removed").exists());
+    assert!(!rootfs.join("etc/.wh.removed").exists());
+    assert!(!rootfs.join("opt/app/old").exists());
+    assert!(rootfs.join("opt/app/new").exists());
+  }
+
+  #[test]
+  fn build_image_from_layout() {
+    let dir = fixture_layout("oci-build");
+    let image = build_oci_image(&dir.join("alpine-app"), None, &dir.join("out")).unwrap();
+
+    let metadata = validate_image_tarballs(&image.metadata_path, None).unwrap();
+    assert_eq!(metadata.architecture, "aarch64");
+    assert_eq!(metadata.property("release"), Some("latest"));
+    assert_eq!(metadata.property("variant"), Some("oci"));
+    assert!(!dir.join("out").join(format!(".oci-rootfs-{}", std::process::id())).exists());
+  }
+
+  #[test]
+  fn reject_escaping_whiteout() {
+    assert!(apply_whiteout(Path::new("/nonexistent"), "../.wh.passwd").is_err());
+    assert!(apply_whiteout(Path::new("/nonexistent"), "etc/passwd").is_ok());
+
+    let dir = TestDir::new("oci-symlink");
+    fs::create_dir_all(dir.join("host")).unwrap();
+    fs::create_dir_all(dir.join("rootfs")).unwrap();
+    fs::write(dir.join("host/keep"), "").unwrap();
+    std::os::unix::fs::symlink(dir.join("host"), dir.join("rootfs/lib")).unwrap();
+
+    apply_whiteout(&dir.join("rootfs"), "lib/.wh.keep").unwrap();
+    apply_whiteout(&dir.join("rootfs"), "lib/.wh..wh..opq").unwrap();
+    assert!(dir.join("host/keep").exists());
+  }
+
+  /// Lower layer links `lib` outside of the rootfs, the upper one writes `lib/passwd` without a `lib` entry
+  #[test]
+  fn reject_layer_through_lower_symlink() {
+    let dir = TestDir::new("oci-escape");
+    fs::create_dir_all(dir.join("host")).unwrap();
+
+    let layout = dir.join("layout");
+    fs::create_dir_all(layout.join("blobs/sha256")).unwrap();
+    fs::write(layout.join("oci-layout"), r#"{"imageLayoutVersion": "1.0.0"}"#).unwrap();
+
+    fs::create_dir_all(dir.join("base")).unwrap();
+    std::os::unix::fs::symlink(dir.join("host"), dir.join("base/lib")).unwrap();
+    let base = layer(&dir, "base", &[("etc/os-release", "ID=alpine\n")], false);
+
+    fs::create_dir_all(dir.join("upper/lib")).unwrap();
+    fs::write(dir.join("upper/lib/passwd"), "root::0:0::/root:/bin/sh\n").unwrap();
+    let upper = dir.join("upper.tar");
+    template_output("tar", vec!["-cf".to_string(), path_arg(&upper), "-C".to_string(), path_arg(&dir.join("upper")), "lib/passwd".to_string()], "").unwrap();
+
+    let config = dir.join("config.json");
+    fs::write(&config, r#"{"architecture": "amd64", "os": "linux"}"#).unwrap();
+
+    let manifest = serde_json::json!({
+      "schemaVersion": 2,
+      "config": add_blob(&layout, "application/vnd.oci.image.config.v1+json", &config),
+      "layers": [
+        add_blob(&layout, "application/vnd.oci.image.layer.v1.tar", &base),
+        add_blob(&layout, "application/vnd.oci.image.layer.v1.tar", &upper),
+      ],
+    });
+
+    let manifest_file = dir.join("manifest.json");
+    fs::write(&manifest_file, manifest.to_string()).unwrap();
+    let descriptor = add_blob(&layout, "application/vnd.oci.image.manifest.v1+json", &manifest_file);
+    fs::write(layout.join("index.json"), serde_json::json!({ "schemaVersion": 2, "manifests": [descriptor] }).to_string()).unwrap();
+
+    let layout = OciLayout::open(&layout).unwrap();
+    let manifest = layout.manifest(None).unwrap();
+
+    assert!(layout.unpack_rootfs(&manifest, &dir.join("rootfs")).is_err());
+    assert!(!dir.join("host/passwd").exists());
+
+    // The same entry is fine when the layer replaces the symlink with a directory
+    let entries: HashSet<PathBuf> = ["lib", "lib/passwd"].iter().map(|entry| entry_path(entry)).collect();
+    assert!(check_entry(&dir.join("rootfs"), "./lib/passwd", &entries).is_ok());
+  }
+}
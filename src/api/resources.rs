@@ -0,0 +1,201 @@
+//! Hardware inventory of an LXD host as returned by `/1.0/resources`
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct LxdResources {
+  pub cpu: CpuResources,
+  pub memory: MemoryResources,
+  pub storage: StorageResources,
+  pub network: NetworkResources,
+  pub pci: PciResources,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CpuResources {
+  pub architecture: String,
+  pub sockets: Vec<CpuSocket>,
+  pub total: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CpuSocket {
+  pub name: String,
+  pub vendor: String,
+  pub socket: u64,
+  pub cores: Vec<CpuCore>,
+  /// Current frequency in MHz
+  pub frequency: u64,
+  pub frequency_minimum: u64,
+  pub frequency_turbo: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CpuCore {
+  pub core: u64,
+  pub die: u64,
+  pub threads: Vec<CpuThread>,
+  pub frequency: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct CpuThread {
+  pub id: u64,
+  pub numa_node: u64,
+  pub thread: u64,
+  pub online: bool,
+  pub isolated: bool,
+}
+
+/// Memory of the host, sizes are in bytes
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct MemoryResources {
+  pub hugepages_total: u64,
+  pub hugepages_used: u64,
+  pub hugepages_size: u64,
+  pub used: u64,
+  pub total: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct StorageResources {
+  pub disks: Vec<StorageDisk>,
+  pub total: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct StorageDisk {
+  pub id: String,
+  pub device: String,
+  pub model: String,
+  #[serde(rename = "type")]
+  pub tp: String,
+  pub read_only: bool,
+  /// Size in bytes
+  pub size: u64,
+  pub removable: bool,
+  pub numa_node: u64,
+  pub device_path: String,
+  pub block_size: u64,
+  pub rpm: u64,
+  pub serial: String,
+  pub partitions: Vec<DiskPartition>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct DiskPartition {
+  pub id: String,
+  pub device: String,
+  pub read_only: bool,
+  pub size: u64,
+  pub partition: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NetworkResources {
+  pub cards: Vec<NetworkCard>,
+  pub total: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NetworkCard {
+  pub driver: String,
+  pub driver_version: String,
+  pub ports: Vec<NetworkPort>,
+  pub numa_node: u64,
+  pub pci_address: String,
+  pub vendor: String,
+  pub vendor_id: String,
+  pub product: String,
+  pub product_id: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct NetworkPort {
+  pub id: String,
+  pub address: String,
+  pub port: u64,
+  pub protocol: String,
+  pub auto_negotiation: bool,
+  pub link_detected: bool,
+  /// Link speed in Mbit/s
+  pub link_speed: u64,
+  pub link_duplex: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PciResources {
+  pub devices: Vec<PciDevice>,
+  pub total: u64,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct PciDevice {
+  pub driver: String,
+  pub driver_version: String,
+  pub numa_node: u64,
+  pub pci_address: String,
+  pub vendor: String,
+  pub vendor_id: String,
+  pub product: String,
+  pub product_id: String,
+  pub iommu_group: u64,
+}
+
+impl LxdResources {
+  /// Number of online CPU threads
+  pub fn online_threads(&self) -> usize {
+    self.cpu.sockets.iter()
+      .flat_map(|socket| socket.cores.iter())
+      .flat_map(|core| core.threads.iter())
+      .filter(|thread| thread.online)
+      .count()
+  }
+
+  /// Memory which is not used at the moment, in bytes
+  pub fn free_memory(&self) -> u64 {
+    self.memory.total.saturating_sub(self.memory.used)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_resources() {
+    let json = r#"{
+      "cpu": {"architecture": "x86_64", "total": 2, "sockets": [{"name": "Intel(R) Core(TM) i5", "vendor": "GenuineIntel", "socket": 0, "frequency": 2400,
+        "cores": [{"core": 0, "die": 0, "frequency": 2400, "threads": [{"id": 0, "numa_node": 0, "thread": 0, "online": true, "isolated": false}, {"id": 1, "numa_node": 0, "thread": 1, "online": false, "isolated": false}]}]}]},
+      "memory": {"hugepages_total": 0, "hugepages_used": 0, "hugepages_size": 2097152, "used": 1073741824, "total": 4294967296},
+      "network": {"total": 1, "cards": [{"driver": "e1000e", "pci_address": "0000:00:1f.6", "vendor_id": "8086", "ports": [{"id": "eth0", "address": "52:54:00:12:34:56", "port": 0, "protocol": "ethernet", "link_detected": true, "link_speed": 1000, "link_duplex": "full"}]}]},
+      "storage": {"total": 1, "disks": [{"id": "nvme0n1", "device": "259:0", "model": "Samsung SSD", "type": "nvme", "size": 512110190592, "partitions": [{"id": "nvme0n1p1", "device": "259:1", "size": 536870912, "partition": 1}]}]},
+      "pci": {"total": 1, "devices": [{"driver": "e1000e", "pci_address": "0000:00:1f.6", "vendor_id": "8086", "product_id": "15bc", "iommu_group": 12}]},
+      "gpu": {"cards": [], "total": 0}
+    }"#;
+
+    let resources: LxdResources = serde_json::from_str(json).unwrap();
+
+    assert_eq!(resources.cpu.architecture, "x86_64");
+    assert_eq!(resources.online_threads(), 1);
+    assert_eq!(resources.free_memory(), 3221225472);
+    assert_eq!(resources.network.cards[0].ports[0].link_speed, 1000);
+    assert_eq!(resources.storage.disks[0].tp, "nvme");
+    assert_eq!(resources.storage.disks[0].partitions[0].partition, 1);
+    assert_eq!(resources.pci.devices[0].iommu_group, 12);
+  }
+}
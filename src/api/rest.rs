@@ -0,0 +1,185 @@
+//! Minimal client for the LXD REST API over the local unix socket
+
+use std::env;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use serde_json::Value;
+
+use crate::error::LxcError;
+
+/// Path of the local LXD unix socket.
+///
+/// Uses `LXD_SOCKET` or `LXD_DIR` when set, otherwise the snap path if it exists and the
+/// default `/var/lib/lxd` path otherwise.
+pub fn socket_path() -> PathBuf {
+  if let Ok(socket) = env::var("LXD_SOCKET") {
+    return PathBuf::from(socket);
+  }
+
+  if let Ok(dir) = env::var("LXD_DIR") {
+    return Path::new(&dir).join("unix.socket");
+  }
+
+  let snap = PathBuf::from("/var/snap/lxd/common/lxd/unix.socket");
+
+  if snap.exists() {
+    return snap;
+  }
+
+  PathBuf::from("/var/lib/lxd/unix.socket")
+}
+
+/// Send GET request to the local LXD socket and return `metadata` of the response
+pub fn get(path: &str, timeout: Duration) -> Result<Value, LxcError> {
+  let raw = get_raw(path, timeout)?;
+
+  parse_response(&raw)
+}
+
+/// Send POST request with JSON body to the local LXD socket and return `metadata` of the response
+pub fn post(path: &str, body: &Value, timeout: Duration) -> Result<Value, LxcError> {
+  let body = body.to_string();
+  let raw = request_raw("POST", path, &[("Content-Type", "application/json")], body.as_bytes(), Some(timeout))?;
+
+  parse_response(&raw)
+}
+
+/// Send POST request with raw body and extra headers, e.g. to push a file, and return `metadata` of the response
+pub fn post_bytes(path: &str, headers: &[(&str, &str)], body: &[u8], timeout: Duration) -> Result<Value, LxcError> {
+  let raw = request_raw("POST", path, headers, body, Some(timeout))?;
+
+  parse_response(&raw)
+}
+
+/// Send DELETE request to the local LXD socket and return `metadata` of the response
+pub fn delete(path: &str, timeout: Duration) -> Result<Value, LxcError> {
+  let raw = request_raw("DELETE", path, &[], &[], Some(timeout))?;
+
+  parse_response(&raw)
+}
+
+/// Send GET request to the local LXD socket and return the whole HTTP response
+pub fn get_raw(path: &str, timeout: Duration) -> Result<Vec<u8>, LxcError> {
+  request_raw("GET", path, &[], &[], Some(timeout))
+}
+
+/// Wait for the background operation returned by a request and return the finished operation.
+///
+/// `None` waits as long as the operation runs. A failed operation is an `LxcError::Api` with its error.
+pub fn wait_operation(operation: &Value, timeout: Option<Duration>) -> Result<Value, LxcError> {
+  let id = operation["id"].as_str().ok_or_else(|| LxcError::Parse("Response is not a background operation".to_string()))?;
+  let raw = request_raw("GET", &format!("/1.0/operations/{}/wait", id), &[], &[], timeout)?;
+
+  operation_result(parse_response(&raw)?)
+}
+
+/// Finished operation as it is, or its error for failed and cancelled ones
+pub fn operation_result(operation: Value) -> Result<Value, LxcError> {
+  match operation["status_code"].as_u64() {
+    Some(200) => Ok(operation),
+    code => Err(LxcError::Api {
+      code: code.map(|code| code as u16).unwrap_or_default(),
+      message: operation["err"].as_str().unwrap_or_default().to_string(),
+    }),
+  }
+}
+
+fn request_raw(method: &str, path: &str, headers: &[(&str, &str)], body: &[u8], timeout: Option<Duration>) -> Result<Vec<u8>, LxcError> {
+  let mut stream = UnixStream::connect(socket_path())?;
+
+  stream.set_read_timeout(timeout)?;
+  stream.set_write_timeout(timeout)?;
+
+  // HTTP/1.0 keeps the body unchunked and closes the connection after the response
+  write!(stream, "{} {} HTTP/1.0\r\nHost: lxd\r\nUser-Agent: lxc-rust\r\n", method, path)?;
+
+  for (name, value) in headers {
+    write!(stream, "{}: {}\r\n", name, value)?;
+  }
+
+  if !body.is_empty() {
+    write!(stream, "Content-Length: {}\r\n", body.len())?;
+  }
+
+  write!(stream, "\r\n")?;
+  stream.write_all(body)?;
+
+  let mut raw = Vec::new();
+  stream.read_to_end(&mut raw)?;
+
+  Ok(raw)
+}
+
+/// Split raw HTTP response into status code and body
+pub fn split_response(raw: &[u8]) -> Result<(u16, &[u8]), LxcError> {
+  let end = raw.windows(4).position(|w| w == b"\r\n\r\n")
+    .ok_or_else(|| LxcError::Parse("HTTP response has no header terminator".to_string()))?;
+
+  let head = String::from_utf8_lossy(&raw[..end]);
+  let code = head.split_whitespace().nth(1)
+    .and_then(|code| code.parse::<u16>().ok())
+    .ok_or_else(|| LxcError::Parse(format!("Invalid HTTP status line: {}", head.lines().next().unwrap_or_default())))?;
+
+  Ok((code, &raw[end + 4..]))
+}
+
+/// Parse LXD response envelope and return its `metadata`
+pub fn parse_response(raw: &[u8]) -> Result<Value, LxcError> {
+  let (code, body) = split_response(raw)?;
+  let mut response: Value = serde_json::from_slice(body)?;
+
+  if response["type"] == "error" || code >= 400 {
+    return Err(LxcError::Api {
+      code: response["error_code"].as_u64().map(|c| c as u16).unwrap_or(code),
+      message: response["error"].as_str().unwrap_or_default().to_string(),
+    });
+  }
+
+  Ok(response["metadata"].take())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_sync_response() {
+    let raw = b"HTTP/1.0 200 OK\r\nContent-Type: application/json\r\n\r\n{\"type\":\"sync\",\"status\":\"Success\",\"status_code\":200,\"metadata\":{\"environment\":{\"server_name\":\"node1\",\"server_clustered\":false}}}";
+
+    let metadata = parse_response(raw).unwrap();
+
+    assert_eq!(metadata["environment"]["server_name"], "node1");
+  }
+
+  #[test]
+  fn parse_error_response() {
+    let raw = b"HTTP/1.0 404 Not Found\r\n\r\n{\"type\":\"error\",\"error\":\"Member not found\",\"error_code\":404,\"metadata\":null}";
+
+    match parse_response(raw) {
+      Err(LxcError::Api { code, message }) => {
+        assert_eq!(code, 404);
+        assert_eq!(message, "Member not found");
+      },
+      other => panic!("unexpected result: {:?}", other),
+    }
+  }
+
+  #[test]
+  fn finished_operations() {
+    let done = serde_json::json!({"id": "6916c8a6", "status_code": 200, "err": "", "metadata": {"return": 3}});
+    assert_eq!(operation_result(done).unwrap()["metadata"]["return"], 3);
+
+    let failed = serde_json::json!({"id": "6916c8a6", "status_code": 400, "err": "User ID 5000 is not mapped", "metadata": null});
+
+    match operation_result(failed) {
+      Err(LxcError::Api { code, message }) => {
+        assert_eq!(code, 400);
+        assert_eq!(message, "User ID 5000 is not mapped");
+      },
+      other => panic!("unexpected result: {:?}", other),
+    }
+  }
+}
@@ -0,0 +1,211 @@
+//! Typed server configuration keys
+
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+use serde_json::Value;
+
+use crate::error::LxcError;
+
+/// Server configuration keys managed by this crate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ServerConfigKey {
+  CoreHttpsAddress,
+  CoreTrustPassword,
+  ImagesAutoUpdateInterval,
+  ImagesRemoteCacheExpiry,
+  StorageBackupsVolume,
+}
+
+impl ServerConfigKey {
+  pub const ALL: [ServerConfigKey; 5] = [
+    ServerConfigKey::CoreHttpsAddress,
+    ServerConfigKey::CoreTrustPassword,
+    ServerConfigKey::ImagesAutoUpdateInterval,
+    ServerConfigKey::ImagesRemoteCacheExpiry,
+    ServerConfigKey::StorageBackupsVolume,
+  ];
+
+  /// Name of the key as LXD knows it
+  pub fn name(&self) -> &'static str {
+    match self {
+      ServerConfigKey::CoreHttpsAddress => "core.https_address",
+      ServerConfigKey::CoreTrustPassword => "core.trust_password",
+      ServerConfigKey::ImagesAutoUpdateInterval => "images.auto_update_interval",
+      ServerConfigKey::ImagesRemoteCacheExpiry => "images.remote_cache_expiry",
+      ServerConfigKey::StorageBackupsVolume => "storage.backups_volume",
+    }
+  }
+}
+
+impl fmt::Display for ServerConfigKey {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.name())
+  }
+}
+
+impl FromStr for ServerConfigKey {
+  type Err = LxcError;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    ServerConfigKey::ALL.iter()
+      .find(|key| key.name() == s)
+      .copied()
+      .ok_or_else(|| LxcError::Invalid(format!("Unknown server configuration key {}", s)))
+  }
+}
+
+/// Value of a server configuration key
+#[derive(Debug, Clone, PartialEq)]
+pub enum ServerConfig {
+  /// Address to bind the API to, e.g. `:8443` or `[::]:8443`
+  CoreHttpsAddress(String),
+  /// LXD only reports whether a password is set, so read value is `"true"`
+  CoreTrustPassword(String),
+  /// Interval in hours, `0` disables auto update
+  ImagesAutoUpdateInterval(u64),
+  /// Number of days after which unused cached image is removed
+  ImagesRemoteCacheExpiry(u64),
+  StorageBackupsVolume { pool: String, volume: String },
+}
+
+impl ServerConfig {
+  pub fn key(&self) -> ServerConfigKey {
+    match self {
+      ServerConfig::CoreHttpsAddress(_) => ServerConfigKey::CoreHttpsAddress,
+      ServerConfig::CoreTrustPassword(_) => ServerConfigKey::CoreTrustPassword,
+      ServerConfig::ImagesAutoUpdateInterval(_) => ServerConfigKey::ImagesAutoUpdateInterval,
+      ServerConfig::ImagesRemoteCacheExpiry(_) => ServerConfigKey::ImagesRemoteCacheExpiry,
+      ServerConfig::StorageBackupsVolume { .. } => ServerConfigKey::StorageBackupsVolume,
+    }
+  }
+
+  /// Value in the form `lxc config set` expects it
+  pub fn value(&self) -> String {
+    match self {
+      ServerConfig::CoreHttpsAddress(address) => address.to_string(),
+      ServerConfig::CoreTrustPassword(password) => password.to_string(),
+      ServerConfig::ImagesAutoUpdateInterval(hours) => hours.to_string(),
+      ServerConfig::ImagesRemoteCacheExpiry(days) => days.to_string(),
+      ServerConfig::StorageBackupsVolume { pool, volume } => format!("{}/{}", pool, volume),
+    }
+  }
+
+  /// Parse and validate raw value of the key
+  pub fn parse(key: ServerConfigKey, value: &str) -> Result<Self, LxcError> {
+    let config = match key {
+      ServerConfigKey::CoreHttpsAddress => ServerConfig::CoreHttpsAddress(value.to_string()),
+      ServerConfigKey::CoreTrustPassword => ServerConfig::CoreTrustPassword(value.to_string()),
+      ServerConfigKey::ImagesAutoUpdateInterval => ServerConfig::ImagesAutoUpdateInterval(parse_number(key, value)?),
+      ServerConfigKey::ImagesRemoteCacheExpiry => ServerConfig::ImagesRemoteCacheExpiry(parse_number(key, value)?),
+      ServerConfigKey::StorageBackupsVolume => match value.split_once('/') {
+        Some((pool, volume)) => ServerConfig::StorageBackupsVolume { pool: pool.to_string(), volume: volume.to_string() },
+        None => return Err(LxcError::Invalid(format!("{} must be in form <pool>/<volume>, got {}", key, value))),
+      },
+    };
+
+    config.validate()?;
+
+    Ok(config)
+  }
+
+  /// Check value before it is sent to the server
+  pub fn validate(&self) -> Result<(), LxcError> {
+    match self {
+      ServerConfig::CoreHttpsAddress(address) => validate_address(address),
+      ServerConfig::CoreTrustPassword(password) if password.is_empty() => {
+        Err(LxcError::Invalid("core.trust_password can't be empty, unset the key instead".to_string()))
+      },
+      ServerConfig::StorageBackupsVolume { pool, volume } if pool.is_empty() || volume.is_empty() || volume.contains('/') => {
+        Err(LxcError::Invalid(format!("Invalid storage.backups_volume {}", self.value())))
+      },
+      _ => Ok(()),
+    }
+  }
+}
+
+fn parse_number(key: ServerConfigKey, value: &str) -> Result<u64, LxcError> {
+  value.parse::<u64>().map_err(|_| LxcError::Invalid(format!("{} must be a non-negative integer, got {}", key, value)))
+}
+
+fn validate_address(address: &str) -> Result<(), LxcError> {
+  let invalid = || LxcError::Invalid(format!("Invalid core.https_address {}", address));
+
+  if address.is_empty() || address.contains(char::is_whitespace) {
+    return Err(invalid());
+  }
+
+  // Bracketed IPv6 host with optional port, e.g. [::]:8443
+  let port = if let Some(rest) = address.strip_prefix('[') {
+    let (_, port) = rest.split_once(']').ok_or_else(invalid)?;
+
+    match port.strip_prefix(':') {
+      Some(port) => Some(port),
+      None if port.is_empty() => None,
+      None => return Err(invalid()),
+    }
+  } else if address.matches(':').count() > 1 {
+    // Bare IPv6 address without port
+    None
+  } else {
+    address.split_once(':').map(|(_, port)| port)
+  };
+
+  match port {
+    Some(port) if port.parse::<u16>().map(|p| p == 0).unwrap_or(true) => Err(invalid()),
+    _ => Ok(()),
+  }
+}
+
+/// Convert `config` object of `/1.0` into a map of strings
+pub fn parse_server_config(server: &Value) -> HashMap<String, String> {
+  server["config"].as_object()
+    .map(|config| config.iter().map(|(key, value)| {
+      let value = match value {
+        Value::String(s) => s.to_string(),
+        other => other.to_string(),
+      };
+
+      (key.to_string(), value)
+    }).collect())
+    .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_known_keys() {
+    assert_eq!("images.remote_cache_expiry".parse::<ServerConfigKey>().unwrap(), ServerConfigKey::ImagesRemoteCacheExpiry);
+    assert!("core.unknown".parse::<ServerConfigKey>().is_err());
+
+    assert_eq!(ServerConfig::parse(ServerConfigKey::ImagesAutoUpdateInterval, "6").unwrap(), ServerConfig::ImagesAutoUpdateInterval(6));
+    assert!(ServerConfig::parse(ServerConfigKey::ImagesAutoUpdateInterval, "-1").is_err());
+
+    let volume = ServerConfig::parse(ServerConfigKey::StorageBackupsVolume, "default/backups").unwrap();
+    assert_eq!(volume.value(), "default/backups");
+    assert!(ServerConfig::parse(ServerConfigKey::StorageBackupsVolume, "backups").is_err());
+  }
+
+  #[test]
+  fn validate_https_address() {
+    for address in [":8443", "10.0.0.1:8443", "[::]:8443", "[2001:db8::1]", "2001:db8::1", "lxd.example.com"] {
+      assert!(ServerConfig::CoreHttpsAddress(address.to_string()).validate().is_ok(), "{}", address);
+    }
+
+    for address in ["", ":0", "host:port", "[::]8443", "10.0.0.1:70000"] {
+      assert!(ServerConfig::CoreHttpsAddress(address.to_string()).validate().is_err(), "{}", address);
+    }
+  }
+
+  #[test]
+  fn server_config_map() {
+    let server: Value = serde_json::from_str(r#"{"config": {"core.https_address": ":8443", "core.trust_password": true}}"#).unwrap();
+    let config = parse_server_config(&server);
+
+    assert_eq!(config["core.https_address"], ":8443");
+    assert_eq!(config["core.trust_password"], "true");
+  }
+}
@@ -0,0 +1,313 @@
+//! Parser for simplestreams image indexes (`streams/v1/index.json` and `images.json`)
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::PathBuf;
+
+use serde::{Deserialize, Serialize};
+
+use crate::api::image::ImageType;
+use crate::error::LxcError;
+use crate::template::template_output;
+
+pub const INDEX_PATH: &str = "streams/v1/index.json";
+
+/// Place simplestreams files are read from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StreamsSource {
+  /// Local copy of the tree, e.g. a cached mirror
+  Directory(PathBuf),
+  /// HTTP(S) server, e.g. `https://images.linuxcontainers.org`
+  Url(String),
+}
+
+impl StreamsSource {
+  /// Read file of the tree, HTTP sources are fetched with curl
+  pub fn read(&self, path: &str) -> Result<String, LxcError> {
+    match self {
+      StreamsSource::Directory(dir) => Ok(fs::read_to_string(dir.join(path))?),
+      StreamsSource::Url(_) => template_output("curl", vec!["-fsSL".to_string(), self.url(path)], "Failed to download simplestreams file"),
+    }
+  }
+
+  /// Location of the file of the tree
+  pub fn url(&self, path: &str) -> String {
+    match self {
+      StreamsSource::Directory(dir) => dir.join(path).to_string_lossy().to_string(),
+      StreamsSource::Url(base) => format!("{}/{}", base.trim_end_matches('/'), path.trim_start_matches('/')),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct StreamsIndex {
+  pub format: String,
+  pub updated: String,
+  pub index: BTreeMap<String, IndexEntry>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct IndexEntry {
+  pub datatype: String,
+  pub path: String,
+  pub format: String,
+  pub updated: String,
+  pub products: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProductsCatalog {
+  pub content_id: String,
+  pub datatype: String,
+  pub format: String,
+  pub updated: String,
+  pub products: BTreeMap<String, Product>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Product {
+  /// Comma separated aliases, see `Product::aliases`
+  pub aliases: String,
+  pub arch: String,
+  pub os: String,
+  pub release: String,
+  pub release_title: String,
+  pub variant: String,
+  pub versions: BTreeMap<String, ProductVersion>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProductVersion {
+  pub items: BTreeMap<String, ProductItem>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ProductItem {
+  pub ftype: String,
+  pub path: String,
+  pub sha256: String,
+  pub size: u64,
+  /// Fingerprint of unified image built from this metadata tarball
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub combined_sha256: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub combined_squashfs_sha256: Option<String>,
+  #[serde(skip_serializing_if = "Option::is_none")]
+  pub combined_rootxz_sha256: Option<String>,
+  #[serde(rename = "combined_disk-kvm-img_sha256", skip_serializing_if = "Option::is_none")]
+  pub combined_disk_kvm_img_sha256: Option<String>,
+}
+
+/// Image an alias points to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResolvedImage {
+  pub product: String,
+  pub version: String,
+  pub tp: ImageType,
+  pub fingerprint: String,
+  pub metadata_url: String,
+  /// Rootfs or disk of split images, `None` for unified images
+  pub rootfs_url: Option<String>,
+  /// Size of all files to download, in bytes
+  pub size: u64,
+}
+
+impl Product {
+  pub fn aliases(&self) -> Vec<&str> {
+    self.aliases.split(',').map(|alias| alias.trim()).filter(|alias| !alias.is_empty()).collect()
+  }
+
+  /// Aliases as LXD exposes them: with the architecture suffix and without it for the host architecture
+  pub fn lxd_aliases(&self) -> Vec<String> {
+    let host = host_architecture();
+
+    self.aliases().into_iter()
+      .flat_map(|alias| {
+        let mut names = vec![format!("{}/{}", alias, self.arch)];
+
+        if self.arch == host {
+          names.push(alias.to_string());
+        }
+
+        names
+      })
+      .collect()
+  }
+
+  /// Newest version which has files for the image type
+  pub fn latest_version(&self, tp: ImageType) -> Option<(&String, &ProductVersion)> {
+    self.versions.iter().rev().find(|(_, version)| version.files(tp).is_some())
+  }
+}
+
+impl ProductVersion {
+  /// Metadata item, rootfs item and fingerprint of the image type
+  pub fn files(&self, tp: ImageType) -> Option<(&ProductItem, Option<&ProductItem>, String)> {
+    if let Some(unified) = self.items.values().find(|item| item.ftype == "lxd_combined.tar.gz") {
+      return match tp {
+        ImageType::Container => Some((unified, None, unified.sha256.to_string())),
+        ImageType::VirtualMachine => None,
+      };
+    }
+
+    let metadata = self.items.values().find(|item| item.ftype == "lxd.tar.xz")?;
+    let by_type = |ftype: &str| self.items.values().find(|item| item.ftype == ftype);
+
+    let (rootfs, fingerprint) = match tp {
+      ImageType::Container => match (by_type("squashfs"), &metadata.combined_squashfs_sha256) {
+        (Some(rootfs), Some(fingerprint)) => (rootfs, fingerprint),
+        _ => (by_type("root.tar.xz")?, metadata.combined_rootxz_sha256.as_ref()?),
+      },
+      ImageType::VirtualMachine => (by_type("disk-kvm.img")?, metadata.combined_disk_kvm_img_sha256.as_ref()?),
+    };
+
+    Some((metadata, Some(rootfs), fingerprint.to_string()))
+  }
+}
+
+/// Architecture of the host in simplestreams naming
+pub fn host_architecture() -> &'static str {
+  simplestreams_architecture(std::env::consts::ARCH)
+}
+
+/// Simplestreams name of a kernel architecture name, e.g. `amd64` for `x86_64`.
+///
+/// Rust names of the host architecture where they differ from the kernel ones, e.g. `x86`, map the same way.
+pub fn simplestreams_architecture(architecture: &str) -> &str {
+  match architecture {
+    "x86_64" => "amd64",
+    "aarch64" => "arm64",
+    "i686" | "x86" => "i386",
+    "armv7l" | "arm" => "armhf",
+    "ppc64le" | "powerpc64" => "ppc64el",
+    other => other,
+  }
+}
+
+/// Loaded simplestreams tree
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimpleStreams {
+  pub source: StreamsSource,
+  pub index: StreamsIndex,
+  pub catalog: ProductsCatalog,
+}
+
+impl SimpleStreams {
+  /// Read index and the `image-downloads` products of the source
+  pub fn load(source: StreamsSource) -> Result<Self, LxcError> {
+    let index: StreamsIndex = serde_json::from_str(&source.read(INDEX_PATH)?)?;
+
+    let entry = index.index.values()
+      .find(|entry| entry.datatype == "image-downloads")
+      .ok_or_else(|| LxcError::Parse("Simplestreams index has no image-downloads entry".to_string()))?;
+
+    let catalog: ProductsCatalog = serde_json::from_str(&source.read(&entry.path)?)?;
+
+    Ok(Self { source, index, catalog })
+  }
+
+  /// Resolve alias such as `ubuntu/jammy/amd64` to the newest image of the type
+  pub fn resolve(&self, alias: &str, tp: ImageType) -> Option<ResolvedImage> {
+    self.catalog.products.iter()
+      .filter(|(_, product)| product.lxd_aliases().iter().any(|name| name == alias))
+      .find_map(|(name, product)| self.resolve_product(name, product, tp))
+  }
+
+  /// Newest images of products whose name or aliases contain the term
+  pub fn search(&self, term: &str, tp: ImageType) -> Vec<ResolvedImage> {
+    self.catalog.products.iter()
+      .filter(|(name, product)| name.contains(term) || product.aliases().iter().any(|alias| alias.contains(term)))
+      .filter_map(|(name, product)| self.resolve_product(name, product, tp))
+      .collect()
+  }
+
+  /// Fingerprints of all images in the tree with their products
+  pub fn fingerprints(&self) -> HashMap<String, String> {
+    self.catalog.products.iter()
+      .flat_map(|(name, product)| product.versions.values().flat_map(move |version| {
+        [ImageType::Container, ImageType::VirtualMachine].into_iter()
+          .filter_map(move |tp| version.files(tp).map(|(_, _, fingerprint)| (fingerprint, name.to_string())))
+      }))
+      .collect()
+  }
+
+  fn resolve_product(&self, name: &str, product: &Product, tp: ImageType) -> Option<ResolvedImage> {
+    let (version, files) = product.latest_version(tp)?;
+    let (metadata, rootfs, fingerprint) = files.files(tp)?;
+
+    Some(ResolvedImage {
+      product: name.to_string(),
+      version: version.to_string(),
+      tp,
+      fingerprint,
+      metadata_url: self.source.url(&metadata.path),
+      rootfs_url: rootfs.map(|rootfs| self.source.url(&rootfs.path)),
+      size: metadata.size + rootfs.map(|rootfs| rootfs.size).unwrap_or_default(),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use std::path::Path;
+
+  fn fixture() -> SimpleStreams {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/simplestreams");
+
+    SimpleStreams::load(StreamsSource::Directory(dir)).unwrap()
+  }
+
+  #[test]
+  fn load_fixture() {
+    let streams = fixture();
+
+    assert_eq!(streams.index.index["images"].products.len(), 2);
+    assert_eq!(streams.catalog.products.len(), 2);
+    assert_eq!(streams.catalog.products["ubuntu:jammy:amd64:default"].aliases(), vec!["ubuntu/jammy/default", "ubuntu/jammy", "ubuntu/22.04"]);
+  }
+
+  #[test]
+  fn resolve_alias() {
+    let streams = fixture();
+
+    let jammy = streams.resolve("ubuntu/jammy/amd64", ImageType::Container).unwrap();
+    assert_eq!(jammy.version, "20220823_07:43");
+    assert_eq!(jammy.fingerprint, "fc1727a922490000000000000000000000000000000000000000000000000000");
+    assert!(jammy.metadata_url.ends_with("20220823_07:43/lxd.tar.xz"));
+    assert!(jammy.rootfs_url.unwrap().ends_with("rootfs.squashfs"));
+    assert_eq!(jammy.size, 872 + 118762496);
+
+    let vm = streams.resolve("ubuntu/22.04/amd64", ImageType::VirtualMachine).unwrap();
+    assert_eq!(vm.fingerprint, "bbbb000000000000000000000000000000000000000000000000000000000000");
+
+    let alpine = streams.resolve("alpine/3.16/arm64", ImageType::Container).unwrap();
+    assert_eq!(alpine.fingerprint, "cccc000000000000000000000000000000000000000000000000000000000000");
+
+    assert!(streams.resolve("alpine/3.16/arm64", ImageType::VirtualMachine).is_none());
+    assert!(streams.resolve("debian/bookworm/amd64", ImageType::Container).is_none());
+  }
+
+  #[test]
+  fn search_and_url() {
+    let streams = fixture();
+
+    assert_eq!(streams.search("alpine", ImageType::Container).len(), 1);
+    assert_eq!(streams.fingerprints().len(), 4);
+    assert_eq!(StreamsSource::Url("https://images.example.com/".to_string()).url(INDEX_PATH), "https://images.example.com/streams/v1/index.json");
+  }
+
+  #[test]
+  fn architecture_names() {
+    assert_eq!(simplestreams_architecture("x86_64"), "amd64");
+    assert_eq!(simplestreams_architecture("i686"), simplestreams_architecture("x86"));
+    assert_eq!(simplestreams_architecture("ppc64le"), "ppc64el");
+    assert_eq!(simplestreams_architecture("s390x"), "s390x");
+  }
+}
@@ -0,0 +1,148 @@
+//! Results of SQL queries run against the LXD database
+
+use serde::Deserialize;
+use serde_json::Value;
+
+use crate::error::LxcError;
+
+/// Database the query is run against
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqlDatabase {
+  /// Database of the member itself
+  Local,
+  /// Cluster-wide database
+  Global,
+}
+
+impl SqlDatabase {
+  pub fn name(&self) -> &'static str {
+    match self {
+      SqlDatabase::Local => "local",
+      SqlDatabase::Global => "global",
+    }
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+  Null,
+  Integer(i64),
+  Real(f64),
+  Text(String),
+}
+
+impl From<Value> for SqlValue {
+  fn from(value: Value) -> Self {
+    match value {
+      Value::Null => SqlValue::Null,
+      Value::Bool(b) => SqlValue::Integer(b as i64),
+      Value::Number(n) => n.as_i64().map(SqlValue::Integer).unwrap_or_else(|| SqlValue::Real(n.as_f64().unwrap_or_default())),
+      Value::String(s) => SqlValue::Text(s),
+      other => SqlValue::Text(other.to_string()),
+    }
+  }
+}
+
+/// Result of one statement
+#[derive(Debug, Clone, PartialEq)]
+pub struct SqlResult {
+  pub columns: Vec<String>,
+  pub rows: Vec<Vec<SqlValue>>,
+  pub rows_affected: i64,
+}
+
+impl SqlResult {
+  /// Value of the column in the row
+  pub fn get(&self, row: usize, column: &str) -> Option<&SqlValue> {
+    let index = self.columns.iter().position(|name| name == column)?;
+
+    self.rows.get(row)?.get(index)
+  }
+}
+
+#[derive(Deserialize)]
+struct RawBatch {
+  #[serde(default)]
+  results: Vec<RawResult>,
+}
+
+#[derive(Deserialize)]
+struct RawResult {
+  #[serde(default)]
+  columns: Vec<String>,
+  #[serde(default)]
+  rows: Vec<Vec<Value>>,
+  #[serde(default)]
+  rows_affected: i64,
+}
+
+/// Parse metadata of `/internal/sql` response
+pub fn parse_sql_results(metadata: Value) -> Result<Vec<SqlResult>, LxcError> {
+  let batch: RawBatch = serde_json::from_value(metadata)?;
+
+  Ok(batch.results.into_iter().map(|result| SqlResult {
+    columns: result.columns,
+    rows: result.rows.into_iter().map(|row| row.into_iter().map(SqlValue::from).collect()).collect(),
+    rows_affected: result.rows_affected,
+  }).collect())
+}
+
+const WRITE_KEYWORDS: [&str; 12] = ["INSERT", "UPDATE", "DELETE", "REPLACE", "CREATE", "DROP", "ALTER", "ATTACH", "DETACH", "VACUUM", "REINDEX", "PRAGMA"];
+
+/// Check that every statement of the query only reads data
+pub fn is_read_only_query(query: &str) -> bool {
+  // `.dump` and `.schema` are handled by LXD itself
+  if matches!(query.trim(), ".dump" | ".schema") {
+    return true;
+  }
+
+  query.split(';')
+    .map(strip_comments)
+    .filter(|statement| !statement.trim().is_empty())
+    .all(|statement| {
+      let words: Vec<String> = statement.split(|c: char| !c.is_alphanumeric() && c != '_')
+        .filter(|word| !word.is_empty())
+        .map(|word| word.to_uppercase())
+        .collect();
+
+      matches!(words.first().map(|word| word.as_str()), Some("SELECT") | Some("WITH") | Some("EXPLAIN"))
+        && !words.iter().any(|word| WRITE_KEYWORDS.contains(&word.as_str()))
+    })
+}
+
+fn strip_comments(statement: &str) -> String {
+  statement.lines()
+    .map(|line| line.split("--").next().unwrap_or_default())
+    .collect::<Vec<_>>()
+    .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_results() {
+    let metadata: Value = serde_json::from_str(r#"{"results": [{"type": "select", "columns": ["id", "name", "description"], "rows": [[1, "c1", null], [2, "c2", "web"]], "rows_affected": 0}]}"#).unwrap();
+
+    let results = parse_sql_results(metadata).unwrap();
+
+    assert_eq!(results[0].rows.len(), 2);
+    assert_eq!(results[0].get(1, "name"), Some(&SqlValue::Text("c2".to_string())));
+    assert_eq!(results[0].get(0, "description"), Some(&SqlValue::Null));
+    assert_eq!(results[0].get(0, "id"), Some(&SqlValue::Integer(1)));
+    assert_eq!(results[0].get(0, "missing"), None);
+  }
+
+  #[test]
+  fn read_only_queries() {
+    assert!(is_read_only_query("SELECT * FROM instances"));
+    assert!(is_read_only_query("  select name from nodes; SELECT 1;"));
+    assert!(is_read_only_query("-- comment\nSELECT 1"));
+    assert!(is_read_only_query(".dump"));
+
+    assert!(!is_read_only_query("DELETE FROM warnings"));
+    assert!(!is_read_only_query("SELECT 1; UPDATE nodes SET name='x'"));
+    assert!(!is_read_only_query("WITH x AS (SELECT 1) DELETE FROM nodes"));
+  }
+}
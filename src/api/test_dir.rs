@@ -0,0 +1,38 @@
+//! Temporary directories for tests
+
+use std::fs;
+use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Empty directory under the system temp dir, removed on drop even when an assertion fails first
+pub struct TestDir {
+  path: PathBuf,
+}
+
+impl TestDir {
+  pub fn new(name: &str) -> Self {
+    let path = std::env::temp_dir().join(format!("lxc-rust-{}-{}-{}", name, process::id(), NEXT_ID.fetch_add(1, Ordering::Relaxed)));
+    let _ = fs::remove_dir_all(&path);
+    fs::create_dir_all(&path).unwrap();
+
+    Self { path }
+  }
+}
+
+impl Deref for TestDir {
+  type Target = Path;
+
+  fn deref(&self) -> &Path {
+    &self.path
+  }
+}
+
+impl Drop for TestDir {
+  fn drop(&mut self) {
+    let _ = fs::remove_dir_all(&self.path);
+  }
+}
@@ -0,0 +1,96 @@
+//! Unix timestamps and the date formats LXD and simplestreams use
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current unix timestamp
+pub fn now() -> i64 {
+  SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs() as i64).unwrap_or_default()
+}
+
+/// Split unix timestamp into `YYYY-MM-DD` and `HH:MM:SS` in UTC
+pub fn format_timestamp(timestamp: i64) -> (String, String) {
+  let (days, seconds) = (timestamp.div_euclid(86400), timestamp.rem_euclid(86400));
+
+  // Days to civil date, see http://howardhinnant.github.io/date_algorithms.html
+  let z = days + 719468;
+  let era = z.div_euclid(146097);
+  let doe = z.rem_euclid(146097);
+  let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+  let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+  let mp = (5 * doy + 2) / 153;
+  let day = doy - (153 * mp + 2) / 5 + 1;
+  let month = if mp < 10 { mp + 3 } else { mp - 9 };
+  let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+
+  (format!("{:04}-{:02}-{:02}", year, month, day), format!("{:02}:{:02}:{:02}", seconds / 3600, seconds % 3600 / 60, seconds % 60))
+}
+
+/// Format unix timestamp like `Tue, 23 Aug 2022 12:00:00 +0000`
+pub fn format_rfc2822(timestamp: i64) -> String {
+  const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+  const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+  let (date, time) = format_timestamp(timestamp);
+  let parts: Vec<usize> = date.split('-').map(|part| part.parse().unwrap_or_default()).collect();
+
+  format!("{}, {:02} {} {} {} +0000", WEEKDAYS[timestamp.div_euclid(86400).rem_euclid(7) as usize], parts[2], MONTHS[parts[1] - 1], parts[0], time)
+}
+
+/// Format unix timestamp like `2022-08-23T08:00:00Z`
+pub fn format_rfc3339(timestamp: i64) -> String {
+  let (date, time) = format_timestamp(timestamp);
+
+  format!("{}T{}Z", date, time)
+}
+
+/// Parse RFC 3339 date such as `2021-03-23T17:38:37.753398689-04:00` into unix timestamp
+pub fn parse_rfc3339(date: &str) -> Option<i64> {
+  let (day, time) = date.split_once('T')?;
+  let mut day_parts = day.splitn(3, '-').map(|part| part.parse::<i64>().ok());
+  let (year, month, day) = (day_parts.next()??, day_parts.next()??, day_parts.next()??);
+
+  let (time, offset) = match time.find(['Z', 'z', '+', '-']) {
+    Some(index) => time.split_at(index),
+    None => (time, ""),
+  };
+
+  let mut time_parts = time.split('.').next()?.splitn(3, ':').map(|part| part.parse::<i64>().ok());
+  let (hour, minute, second) = (time_parts.next()??, time_parts.next()??, time_parts.next()??);
+
+  let offset = match offset.chars().next() {
+    None | Some('Z') | Some('z') => 0,
+    Some(sign) => {
+      let (hours, minutes) = offset[1..].split_once(':')?;
+      let offset = hours.parse::<i64>().ok()? * 3600 + minutes.parse::<i64>().ok()? * 60;
+
+      if sign == '-' { -offset } else { offset }
+    },
+  };
+
+  // Civil date to days, see http://howardhinnant.github.io/date_algorithms.html
+  let y = if month <= 2 { year - 1 } else { year };
+  let era = y.div_euclid(400);
+  let yoe = y.rem_euclid(400);
+  let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+  let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+  let days = era * 146097 + doe - 719468;
+
+  Some(days * 86400 + hour * 3600 + minute * 60 + second - offset)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn timestamps() {
+    assert_eq!(format_timestamp(1661241600), ("2022-08-23".to_string(), "08:00:00".to_string()));
+    assert_eq!(format_rfc2822(0), "Thu, 01 Jan 1970 00:00:00 +0000");
+
+    assert_eq!(parse_rfc3339("2022-08-23T08:00:00Z"), Some(1661241600));
+    assert_eq!(parse_rfc3339("2022-08-23T04:00:00.753398689-04:00"), Some(1661241600));
+    assert_eq!(parse_rfc3339("0001-01-01T00:00:00Z"), Some(-62135596800));
+    assert_eq!(parse_rfc3339("yesterday"), None);
+    assert_eq!(format_rfc3339(1661241600), "2022-08-23T08:00:00Z");
+  }
+}
@@ -0,0 +1,78 @@
+//! Server warnings as returned by `/1.0/warnings`
+
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WarningStatus {
+  New,
+  Acknowledged,
+  Resolved,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WarningSeverity {
+  Low,
+  Moderate,
+  High,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct LxdWarning {
+  pub uuid: String,
+  /// Cluster member the warning was raised on, empty on standalone servers
+  #[serde(default)]
+  pub location: String,
+  #[serde(default)]
+  pub project: String,
+  #[serde(rename = "type")]
+  pub tp: String,
+  #[serde(default)]
+  pub count: u64,
+  pub first_seen_at: String,
+  pub last_seen_at: String,
+  pub last_message: String,
+  pub severity: WarningSeverity,
+  pub status: WarningStatus,
+  /// API path of the affected entity, e.g. `/1.0/instances/c1?project=default`
+  #[serde(default)]
+  pub entity_url: String,
+}
+
+impl LxdWarning {
+  /// Kind and name of the affected entity, e.g. `("instances", "c1")`
+  pub fn entity(&self) -> Option<(&str, &str)> {
+    let path = self.entity_url.split('?').next()?.strip_prefix("/1.0/")?;
+
+    path.split_once('/')
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn parse_warnings() {
+    let json = r#"[{
+      "uuid": "e9e9da0d-2538-4351-8047-46d4a8ae4dbb",
+      "location": "node1",
+      "project": "default",
+      "type": "Couldn't find the CGroup blkio.weight",
+      "count": 2,
+      "first_seen_at": "2021-03-23T17:38:37.753398689-04:00",
+      "last_seen_at": "2021-03-23T17:38:37.753398689-04:00",
+      "last_message": "Couldn't find the CGroup blkio.weight, disk priority will be ignored",
+      "severity": "low",
+      "status": "new",
+      "entity_url": "/1.0/instances/c1?project=default"
+    }]"#;
+
+    let warnings: Vec<LxdWarning> = serde_json::from_str(json).unwrap();
+
+    assert_eq!(warnings[0].status, WarningStatus::New);
+    assert_eq!(warnings[0].severity, WarningSeverity::Low);
+    assert_eq!(warnings[0].entity(), Some(("instances", "c1")));
+  }
+}
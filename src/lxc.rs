@@ -3,6 +3,8 @@
 //! A library for working with Linux Daemon && Linux Containers
 
 pub use daemon::*;
+pub use cluster::*;
+pub use warning::*;
 pub use image::*;
 pub use container::*; 
 pub use storage::*;
@@ -14,12 +16,82 @@ pub use remote_connection::*;
 pub use config::*;
 pub use network::*;
 pub use snapshot::*;
+pub use error::*;
+pub use metrics::*;
+
+  // Errors
+  pub mod error {
+    use std::fmt;
+
+    /// Error returned by functions that capture output of lxc/lxd
+    #[derive(Debug)]
+    pub enum LxcError {
+      /// Command could not be spawned or socket could not be used
+      Io(std::io::Error),
+      /// Command exited with non-zero status
+      Command { message: String, stderr: String },
+      /// LXD REST API answered with an error
+      Api { code: u16, message: String },
+      /// Output could not be parsed
+      Parse(String),
+      /// Operation did not finish in time
+      Timeout(String),
+      /// Value was rejected before it was sent to LXD
+      Invalid(String),
+      /// Image files do not match the fingerprint of the image
+      FingerprintMismatch { expected: String, actual: String },
+      /// No image has the alias or fingerprint
+      ImageNotFound(String),
+      /// Fingerprint prefix matches several images, `candidates` are their fingerprints
+      AmbiguousImage { image: String, candidates: Vec<String> },
+    }
+
+    impl fmt::Display for LxcError {
+      fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+          LxcError::Io(e) => write!(f, "{}", e),
+          LxcError::Command { message, stderr } => write!(f, "{}: {}", message, stderr),
+          LxcError::Api { code, message } => write!(f, "LXD API error {}: {}", code, message),
+          LxcError::Parse(message) => write!(f, "Failed to parse output: {}", message),
+          LxcError::Timeout(message) => write!(f, "Timed out: {}", message),
+          LxcError::Invalid(message) => write!(f, "{}", message),
+          LxcError::FingerprintMismatch { expected, actual } => write!(f, "Image fingerprint mismatch, expected {} but files hash to {}", expected, actual),
+          LxcError::ImageNotFound(image) => write!(f, "Image {} not found", image),
+          LxcError::AmbiguousImage { image, candidates } => write!(f, "Image {} is ambiguous, it matches {}", image, candidates.join(", ")),
+        }
+      }
+    }
+
+    impl std::error::Error for LxcError {}
+
+    impl From<std::io::Error> for LxcError {
+      fn from(e: std::io::Error) -> Self {
+        LxcError::Io(e)
+      }
+    }
+
+    impl From<serde_json::Error> for LxcError {
+      fn from(e: serde_json::Error) -> Self {
+        LxcError::Parse(e.to_string())
+      }
+    }
+
+    impl From<serde_yaml::Error> for LxcError {
+      fn from(e: serde_yaml::Error) -> Self {
+        LxcError::Parse(e.to_string())
+      }
+    }
+  }
 
   mod template {
-    use std::process::Command;
+    use std::io::{BufReader, Read, Write};
+    use std::path::Path;
+    use std::process::{Command, Stdio};
+    use serde_json::Value;
+    use crate::error::LxcError;
 
     pub fn template(cm: &str, args: Vec<String>, _err_message: &str) {
-      let cmd = Command::new(&cm).args(args).output().unwrap_or_else(|e| {
+      let cmd = Command::new(cm).args(args).output().unwrap_or_else(|e| {
         panic!("{}", &e)
       });
 
@@ -27,11 +99,130 @@ pub use snapshot::*;
 
       print!("{}", result);
     }
+
+    /// Same as `template`, but returns stdout instead of printing it
+    pub fn template_output(cm: &str, args: Vec<String>, err_message: &str) -> Result<String, LxcError> {
+      let cmd = Command::new(cm).args(args).output()?;
+
+      if !cmd.status.success() {
+        return Err(LxcError::Command {
+          message: err_message.to_string(),
+          stderr: String::from_utf8_lossy(&cmd.stderr).trim().to_string()
+        });
+      }
+
+      Ok(String::from_utf8_lossy(&cmd.stdout).to_string())
+    }
+
+    /// Same as `template_output`, but writes `input` to stdin of the command
+    pub fn template_input(cm: &str, args: Vec<String>, input: &str, err_message: &str) -> Result<String, LxcError> {
+      let mut child = Command::new(cm).args(args).stdin(Stdio::piped()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+
+      if let Some(mut stdin) = child.stdin.take() {
+        stdin.write_all(input.as_bytes())?;
+      }
+
+      let cmd = child.wait_with_output()?;
+
+      if !cmd.status.success() {
+        return Err(LxcError::Command {
+          message: err_message.to_string(),
+          stderr: String::from_utf8_lossy(&cmd.stderr).trim().to_string()
+        });
+      }
+
+      Ok(String::from_utf8_lossy(&cmd.stdout).to_string())
+    }
+
+    /// Same as `template_output`, but calls `on_line` with every line or `\r` progress update of stdout
+    pub fn template_lines(cm: &str, args: Vec<String>, on_line: &mut dyn FnMut(&str), err_message: &str) -> Result<String, LxcError> {
+      let mut child = Command::new(cm).args(args).stdin(Stdio::null()).stdout(Stdio::piped()).stderr(Stdio::piped()).spawn()?;
+      let mut output = Vec::new();
+      let mut line = Vec::new();
+
+      if let Some(stdout) = child.stdout.take() {
+        for byte in BufReader::new(stdout).bytes() {
+          let byte = byte?;
+          output.push(byte);
+
+          if byte == b'\r' || byte == b'\n' {
+            if !line.is_empty() {
+              on_line(&String::from_utf8_lossy(&line));
+              line.clear();
+            }
+          } else {
+            line.push(byte);
+          }
+        }
+      }
+
+      if !line.is_empty() {
+        on_line(&String::from_utf8_lossy(&line));
+      }
+
+      let cmd = child.wait_with_output()?;
+
+      if !cmd.status.success() {
+        return Err(LxcError::Command {
+          message: err_message.to_string(),
+          stderr: String::from_utf8_lossy(&cmd.stderr).trim().to_string()
+        });
+      }
+
+      Ok(String::from_utf8_lossy(&output).to_string())
+    }
+
+    /// Path as a command argument
+    pub fn path_arg(path: &Path) -> String {
+      path.to_string_lossy().to_string()
+    }
+
+    /// Send GET request with `lxc query` to the remote and parse the JSON it prints
+    pub fn query(remote: &str, path: &str, err_message: &str) -> Result<Value, LxcError> {
+      let output = template_output("lxc", vec!["query".to_string(), format!("{}:{}", remote.to_string(), path.to_string())], err_message)?;
+
+      Ok(serde_json::from_str(&output)?)
+    }
+  }
+
+  // Typed LXD API
+  pub mod api {
+    pub mod rest;
+    pub mod resources;
+    pub mod server_config;
+    pub mod metrics;
+    pub mod warning;
+    pub mod cluster;
+    pub mod sql;
+    pub mod image;
+    pub mod simplestreams;
+    pub mod fingerprint;
+    pub mod image_metadata;
+    pub mod image_server;
+    pub mod image_builder;
+    pub mod time;
+    pub mod image_gc;
+    pub mod image_copy;
+    pub mod image_sync;
+    pub mod image_properties;
+    pub mod oci;
+    pub mod image_publish;
+    pub mod image_import;
+    pub mod instance;
+    pub mod exec;
+    #[cfg(test)]
+    pub mod test_dir;
   }
 
   // LXdaemon
   pub mod daemon {
-    use crate::template::template;
+    use std::thread;
+    use std::time::{Duration, Instant};
+    use crate::api::rest;
+    use crate::api::resources::LxdResources;
+    use crate::api::sql::{is_read_only_query, parse_sql_results, SqlDatabase, SqlResult};
+    use crate::error::LxcError;
+    use crate::template::{template, template_output, query};
     
     /// Initialize a linux daemon
     pub fn lxd_init() {
@@ -67,55 +258,347 @@ pub use snapshot::*;
     pub fn recover_lxd_instance_lost_quorum() {
       template("lxd", vec!["cluster".to_string(), "recover-from-quorum-loss".to_string()], "Try of recover lxd cluster instance with lost quorum was failed")
     } 
+
+    /// Wait until LXD is ready to serve requests.
+    ///
+    /// Runs `lxd waitready`, then polls the REST socket until `/1.0` answers and,
+    /// in a cluster, the local member is online. Running out of time in either step is `LxcError::Timeout`.
+    pub fn wait_lxd_ready(timeout: Duration) -> Result<(), LxcError> {
+      let deadline = Instant::now() + timeout;
+
+      template_output("lxd", vec!["waitready".to_string(), format!("--timeout={}", timeout.as_secs().max(1))], "LXD was not ready in time").map_err(|e| match e {
+        LxcError::Command { stderr, .. } => LxcError::Timeout(format!("LXD was not ready in {:?}, {}", timeout, stderr)),
+        e => e,
+      })?;
+
+      loop {
+        let remaining = deadline.saturating_duration_since(Instant::now());
+
+        let last_state = match is_local_member_ready(remaining.max(Duration::from_millis(100))) {
+          Ok(true) => return Ok(()),
+          Ok(false) => "local cluster member is not online".to_string(),
+          Err(e) => e.to_string(),
+        };
+
+        if remaining.is_zero() {
+          return Err(LxcError::Timeout(format!("LXD was not ready in {:?}, {}", timeout, last_state)));
+        }
+
+        thread::sleep(remaining.min(Duration::from_millis(500)));
+      }
+    }
+
+    fn is_local_member_ready(timeout: Duration) -> Result<bool, LxcError> {
+      let server = rest::get("/1.0", timeout)?;
+      let environment = &server["environment"];
+
+      if !environment["server_clustered"].as_bool().unwrap_or(false) {
+        return Ok(true);
+      }
+
+      let name = environment["server_name"].as_str().unwrap_or_default();
+      let member = rest::get(&format!("/1.0/cluster/members/{}", name), timeout)?;
+
+      Ok(member["status"].as_str() == Some("Online"))
+    }
+
+    /// Get CPU, memory, disk, network card and PCI inventory of the local LXD host.
+    ///
+    /// `target` selects a cluster member.
+    pub fn get_local_lxd_resources(target: Option<&str>) -> Result<LxdResources, LxcError> {
+      get_remote_lxd_resources("local", target)
+    }
+
+    pub fn get_remote_lxd_resources(remote: &str, target: Option<&str>) -> Result<LxdResources, LxcError> {
+      let path = match target {
+        Some(member) => format!("/1.0/resources?target={}", member),
+        None => "/1.0/resources".to_string(),
+      };
+
+      let resources = query(remote, &path, "Failed to get lxd host resources")?;
+
+      Ok(serde_json::from_value(resources)?)
+    }
+
+    /// Run SQL query against the local or global database of the local LXD, like `lxd sql`.
+    ///
+    /// Statements that modify data are rejected unless `allow_writes` is set.
+    pub fn query_lxd_database(database: SqlDatabase, sql: &str, allow_writes: bool) -> Result<Vec<SqlResult>, LxcError> {
+      if !allow_writes && !is_read_only_query(sql) {
+        return Err(LxcError::Invalid(format!("Refusing to run write query without allow_writes: {}", sql)));
+      }
+
+      let body = serde_json::json!({ "database": database.name(), "query": sql });
+      let results = rest::post("/internal/sql", &body, Duration::from_secs(60))?;
+
+      parse_sql_results(results)
+    }
+  }
+
+  // Cluster groups
+  pub mod cluster {
+    use crate::api::cluster::ClusterGroup;
+    use crate::error::LxcError;
+    use crate::template::{template_output, query};
+
+    /// Get cluster groups with their members
+    pub fn get_local_cluster_groups() -> Result<Vec<ClusterGroup>, LxcError> {
+      get_remote_cluster_groups("local")
+    }
+
+    pub fn get_remote_cluster_groups(remote: &str) -> Result<Vec<ClusterGroup>, LxcError> {
+      let groups = query(remote, "/1.0/cluster/groups?recursion=1", "Failed to get cluster groups")?;
+
+      Ok(serde_json::from_value(groups)?)
+    }
+
+    /// Create new cluster group
+    pub fn create_local_cluster_group(group: &str) -> Result<(), LxcError> {
+      create_remote_cluster_group("local", group)
+    }
+
+    pub fn create_remote_cluster_group(remote: &str, group: &str) -> Result<(), LxcError> {
+      template_output("lxc", vec!["cluster".to_string(), "group".to_string(), "create".to_string(), format!("{}:{}", remote.to_string(), group.to_string())], "Failed to create cluster group")?;
+
+      Ok(())
+    }
+
+    /// Set cluster groups of the member, member is removed from groups which are not listed
+    pub fn assign_local_cluster_groups(member: &str, groups: &[&str]) -> Result<(), LxcError> {
+      assign_remote_cluster_groups("local", member, groups)
+    }
+
+    pub fn assign_remote_cluster_groups(remote: &str, member: &str, groups: &[&str]) -> Result<(), LxcError> {
+      template_output("lxc", vec!["cluster".to_string(), "group".to_string(), "assign".to_string(), format!("{}:{}", remote.to_string(), member.to_string()), groups.join(",")], "Failed to assign cluster groups to member")?;
+
+      Ok(())
+    }
+
+    /// Delete cluster group
+    pub fn del_local_cluster_group(group: &str) -> Result<(), LxcError> {
+      del_remote_cluster_group("local", group)
+    }
+
+    pub fn del_remote_cluster_group(remote: &str, group: &str) -> Result<(), LxcError> {
+      template_output("lxc", vec!["cluster".to_string(), "group".to_string(), "delete".to_string(), format!("{}:{}", remote.to_string(), group.to_string())], "Failed to delete cluster group")?;
+
+      Ok(())
+    }
+  }
+
+  // Server warnings
+  pub mod warning {
+    use crate::api::warning::LxdWarning;
+    use crate::error::LxcError;
+    use crate::template::{template_output, query};
+
+    /// Get warnings of the local LXD, `target` keeps only warnings of that cluster member
+    pub fn get_local_warnings(target: Option<&str>) -> Result<Vec<LxdWarning>, LxcError> {
+      get_remote_warnings("local", target)
+    }
+
+    pub fn get_remote_warnings(remote: &str, target: Option<&str>) -> Result<Vec<LxdWarning>, LxcError> {
+      let warnings = query(remote, "/1.0/warnings?recursion=1", "Failed to get lxd warnings")?;
+      let mut warnings: Vec<LxdWarning> = serde_json::from_value(warnings)?;
+
+      if let Some(member) = target {
+        warnings.retain(|warning| warning.location == member);
+      }
+
+      Ok(warnings)
+    }
+
+    /// Acknowledge warning, so it is not shown as new anymore
+    pub fn acknowledge_local_warning(uuid: &str) -> Result<(), LxcError> {
+      acknowledge_remote_warning("local", uuid)
+    }
+
+    pub fn acknowledge_remote_warning(remote: &str, uuid: &str) -> Result<(), LxcError> {
+      template_output("lxc", vec!["warning".to_string(), "acknowledge".to_string(), format!("{}:{}", remote.to_string(), uuid.to_string())], "Failed to acknowledge warning")?;
+
+      Ok(())
+    }
+
+    /// Delete warning
+    pub fn del_local_warning(uuid: &str) -> Result<(), LxcError> {
+      del_remote_warning("local", uuid)
+    }
+
+    pub fn del_remote_warning(remote: &str, uuid: &str) -> Result<(), LxcError> {
+      template_output("lxc", vec!["warning".to_string(), "delete".to_string(), format!("{}:{}", remote.to_string(), uuid.to_string())], "Failed to delete warning")?;
+
+      Ok(())
+    }
+  }
+
+  // Metrics
+  pub mod metrics {
+    use std::time::SystemTime;
+    use crate::api::metrics::{instance_metrics, parse_metrics, MetricsScrape};
+    use crate::error::LxcError;
+    use crate::template::template_output;
+
+    /// Scrape metrics of the local LXD and group them by instance
+    pub fn get_local_metrics() -> Result<MetricsScrape, LxcError> {
+      get_remote_metrics("local")
+    }
+
+    pub fn get_remote_metrics(remote: &str) -> Result<MetricsScrape, LxcError> {
+      let text = template_output("lxc", vec!["query".to_string(), "--raw".to_string(), format!("{}:/1.0/metrics", remote.to_string())], "Failed to get lxd metrics")?;
+      let taken_at = SystemTime::now();
+
+      Ok(MetricsScrape { taken_at, instances: instance_metrics(&parse_metrics(&text)?) })
+    }
   }
 
   // Images
   pub mod image {
-    use crate::template::template;
+    use std::path::Path;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use crate::api::fingerprint::{find_exported_files, fingerprint_from_file_name, verify_image_fingerprint};
+    use crate::api::image::{resolve_image, ImageAlias, ImageFilter, ImageType, LxcImage};
+    use crate::api::image_copy::ImageCopy;
+    use crate::api::image_sync::{plan_sync, SyncPlan, SyncSelector};
+    use crate::api::image_properties::{image_lifecycle, ImageLifecycle, ImageProperty, ImageSettings};
+    use crate::api::server_config::{ServerConfig, ServerConfigKey};
+    use crate::config::get_remote_server_config_value;
+    use crate::api::image_gc::{base_images, image_projects, plan_gc, GcCandidate, GcPolicy};
+    use crate::api::time::now;
+    use crate::api::image_builder::{validate_image_tarballs, BuiltImage};
+    use crate::api::simplestreams::{ResolvedImage, SimpleStreams, StreamsSource};
+    use crate::api::oci::build_oci_image;
+    use crate::api::image_publish::{parse_published_fingerprint, ImagePublish};
+    use crate::api::image_import::{parse_progress, pty_args, ImageImport, ImageImportSource, ImportProgress};
+    use crate::api::fingerprint::image_fingerprint;
+    use crate::error::LxcError;
+    use crate::template::{template, template_output, template_input, template_lines, query};
     
     /// Get you'r local lxc images
-    pub fn get_local_lxc_images(flags: Option<Vec<&str>>) {
-      template("lxc", vec!["image".to_string(), "list".to_string(), "local:".to_string()], "Try of get lxc was failed");
+    pub fn get_local_lxc_images(filter: &ImageFilter) -> Result<Vec<LxcImage>, LxcError> {
+      get_remote_lxc_images("local", filter)
     }
     
     /// Get images from remote server
-    pub fn get_remote_lxc_images(remote_name: &str) {
-       template("lxc", vec!["image".to_string(), "list".to_string(), format!("{}:", remote_name.to_string())], "Failed to get remote lcx images");
+    pub fn get_remote_lxc_images(remote_name: &str, filter: &ImageFilter) -> Result<Vec<LxcImage>, LxcError> {
+      let output = template_output("lxc", vec!["image".to_string(), "list".to_string(), format!("{}:", remote_name.to_string()), "--format".to_string(), "json".to_string()], "Failed to get remote lcx images")?;
+      let images: Vec<LxcImage> = serde_json::from_str(&output)?;
+
+      Ok(filter.apply(images))
     }
     
     /// Get lxc images from lxc registry
-    pub fn get_registry_lxc_images() {
-      template("lxc", vec!["image".to_string(), "list".to_string(), "images:".to_string()], "Try of get lxc images was failed");
+    pub fn get_registry_lxc_images(filter: &ImageFilter) -> Result<Vec<LxcImage>, LxcError> {
+      get_remote_lxc_images("images", filter)
+    }
+    
+    /// Search lxc images in registry, `image` is a filter as `lxc image list` takes it, e.g. `ubuntu/jammy`
+    pub fn search_lxc_image(image: &str, filter: &ImageFilter) -> Result<Vec<LxcImage>, LxcError> {
+      let output = template_output("lxc", vec!["image".to_string(), "list".to_string(), "images:".to_string(), image.to_string(), "--format".to_string(), "json".to_string()], "Try of get some lxc image was failed")?;
+      let images: Vec<LxcImage> = serde_json::from_str(&output)?;
+
+      Ok(filter.apply(images))
+    }
+
+    /// Search lxc images in simplestreams index, e.g. cached copy of `images:`
+    pub fn search_cached_lxc_image(source: StreamsSource, image: &str, tp: ImageType) -> Result<Vec<ResolvedImage>, LxcError> {
+      Ok(SimpleStreams::load(source)?.search(image, tp))
     }
     
-    /// Search lxc images in registry 
-    pub fn search_lxc_image(image: &str) {
-      template("lxc", vec!["image".to_string(), "list".to_string(), "images:".to_string(), image.to_string()], "Try of get some lxc image was failed");
+    /// Resolve `[<remote>:]<alias, fingerprint or fingerprint prefix>` to exactly one image
+    pub fn resolve_lxc_image(image: &str) -> Result<LxcImage, LxcError> {
+      resolve_filtered_lxc_image(image, &ImageFilter::new())
+    }
+
+    fn resolve_filtered_lxc_image(image: &str, filter: &ImageFilter) -> Result<LxcImage, LxcError> {
+      let (remote, name) = image.split_once(':').unwrap_or(("local", image));
+
+      Ok(resolve_image(&get_matching_lxc_images(remote, name, None, filter)?, name)?.clone())
+    }
+
+    /// Images of the remote whose alias contains `name` or whose fingerprint starts with it, `lxc` filters the listing.
+    ///
+    /// Without a project the images of the default project or the one switched to are listed.
+    fn get_matching_lxc_images(remote: &str, name: &str, project: Option<&str>, filter: &ImageFilter) -> Result<Vec<LxcImage>, LxcError> {
+      let mut args = vec!["image".to_string(), "list".to_string(), format!("{}:", remote)];
+      args.extend(Some(name.to_string()).filter(|name| !name.is_empty()));
+      args.extend(project.iter().flat_map(|project| ["--project".to_string(), project.to_string()]));
+      args.extend(["--format".to_string(), "json".to_string()]);
+
+      let output = template_output("lxc", args, "Failed to get remote lcx images")?;
+      let images: Vec<LxcImage> = serde_json::from_str(&output)?;
+
+      Ok(filter.apply(images))
+    }
+
+    /// Image argument for `lxc`: exact aliases as given, fingerprint prefixes as the full fingerprint
+    fn resolved_lxc_image_arg(image: &str) -> Result<String, LxcError> {
+      resolved_project_lxc_image_arg(image, None)
+    }
+
+    fn resolved_project_lxc_image_arg(image: &str, project: Option<&str>) -> Result<String, LxcError> {
+      let (remote, name) = image.split_once(':').unwrap_or(("local", image));
+      let images = get_matching_lxc_images(remote, name, project, &ImageFilter::new())?;
+
+      if images.iter().any(|candidate| candidate.aliases.iter().any(|alias| alias.name == name)) {
+        return Ok(image.to_string());
+      }
+
+      let prefix = image.split_once(':').map(|(remote, _)| format!("{}:", remote)).unwrap_or_default();
+
+      Ok(format!("{}{}", prefix, resolve_image(&images, name)?.fingerprint))
     }
     
     /// Get more infromation about current lxc image
-    pub fn get_lxc_image_info(image: &str) {
-      template("lxc", vec!["image".to_string(), "info".to_string(), image.to_string()], "Try of getting image information was failed");
+    pub fn get_lxc_image_info(image: &str) -> Result<String, LxcError> {
+      template_output("lxc", vec!["image".to_string(), "info".to_string(), resolved_lxc_image_arg(image)?], "Try of getting image information was failed")
     }
     
     /// Get a tiny infromation about current lxc image
-    pub fn get_lxc_image_show(image: &str) {
-      template("lxc", vec!["image".to_string(), "show".to_string(), image.to_string()], "Try of getting image information was failed");
+    pub fn get_lxc_image_show(image: &str) -> Result<String, LxcError> {
+      template_output("lxc", vec!["image".to_string(), "show".to_string(), resolved_lxc_image_arg(image)?], "Try of getting image information was failed")
     }
     
-    /// Copy lxc image from registry to local with alias
-    pub fn copy_lxc_image(image: &str, alias: &str) {
-      template("lxc", vec!["image".to_string(), "copy".to_string(), format!("images:{}", image.to_string()), "local:".to_string(), "--alias".to_string(), alias.to_string()], "Failed to copy image with alias from remote store to local");
+    /// Copy lxc image between remotes, returns fingerprint of the copied image
+    pub fn copy_lxc_image(copy: &ImageCopy) -> Result<String, LxcError> {
+      let fingerprint = resolve_filtered_lxc_image(&format!("{}:{}", copy.source_remote, copy.image), &ImageFilter::new().tp(copy.tp))?.fingerprint;
+
+      template_output("lxc", copy.to_args(), "Failed to copy image")?;
+
+      Ok(fingerprint)
     }
     
-    /// Copy lxc image from local to remote with alias
-    pub fn copy_lxc_image_to_remote(image: &str, remote: &str, alias: &str) {
-       template("lxc", vec!["image".to_string(), "copy".to_string(), format!("images:{}", image.to_string()), format!("{}:", remote.to_string()), "--alias".to_string(), alias.to_string()], "Failed to copy lxc image from remote to local");
+    /// Compute which images every destination is missing and which it has beyond the source set
+    pub fn plan_lxc_image_sync(source: &str, selectors: &[SyncSelector], destinations: &[&str], prune: bool) -> Result<Vec<SyncPlan>, LxcError> {
+      let source_images = get_remote_lxc_images(source, &ImageFilter::new())?;
+
+      destinations.iter()
+        .map(|destination| plan_sync(destination, &source_images, &get_remote_lxc_images(destination, &ImageFilter::new())?, selectors, prune))
+        .collect()
     }
-    
-    /// Copy lxc image from remote to local with alias
-    pub fn copy_lxc_image_from_remote(image: &str, remote: &str, alias: &str) {
-       template("lxc", vec!["image".to_string(), "copy".to_string(), format!("{}:{}", remote.to_string(), image.to_string()), "local:".to_string(), "--alias".to_string(), alias.to_string()], "Failed to copy lxc image from local to remote");
+
+    /// Mirror images selected on the source remote to the destinations, copying only missing fingerprints.
+    ///
+    /// With `prune` destination images matching the selectors which left the source set are deleted.
+    pub fn sync_lxc_images(source: &str, selectors: &[SyncSelector], destinations: &[&str], prune: bool) -> Result<Vec<SyncPlan>, LxcError> {
+      let plans = plan_lxc_image_sync(source, selectors, destinations, prune)?;
+
+      for plan in &plans {
+        for image in &plan.copy {
+          let copy = ImageCopy::new(source, &image.fingerprint, &plan.destination)
+            .copy_aliases(true)
+            .auto_update(image.auto_update)
+            .public(image.public)
+            .vm(image.tp == ImageType::VirtualMachine);
+
+          copy_lxc_image(&copy)?;
+        }
+
+        for image in &plan.remove {
+          del_lxc_image(&format!("{}:{}", plan.destination, image.fingerprint))?;
+        }
+      }
+
+      Ok(plans)
     }
     
     ///  Publish lxc image
@@ -123,59 +606,399 @@ pub use snapshot::*;
       template("lxc", vec!["publish".to_string(), container.to_string(), "--alias".to_string(), alias.to_string()], "Failed to publish linux container image");
     }
 
+    /// Publish instance or its snapshot with options, returns fingerprint of the new image.
+    ///
+    /// For consistent publish a running instance is stopped first and started again even if the publish fails.
+    pub fn publish_lxc_image_with_options(publish: &ImagePublish) -> Result<String, LxcError> {
+      let instance = format!("{}:{}", publish.remote, publish.instance);
+      let restart = publish.consistent && publish.snapshot.is_none()
+        && query(&publish.remote, &format!("/1.0/instances/{}", publish.instance), "Failed to get instance state")?["status"] == "Running";
+
+      if restart {
+        template_output("lxc", vec!["stop".to_string(), instance.to_string()], "Failed to stop instance for publish")?;
+      }
+
+      let output = template_output("lxc", publish.to_args(), "Failed to publish image");
+
+      if restart {
+        template_output("lxc", vec!["start".to_string(), instance], "Failed to start instance after publish")?;
+      }
+
+      parse_published_fingerprint(&output?).ok_or_else(|| LxcError::Parse("lxc publish printed no fingerprint".to_string()))
+    }
+
     pub fn export_lxc_image(image: &str, name: &str) {
       template("lxc", vec!["image".to_string(), "export".to_string(), image.to_string(), name.to_string()], "Failed to export image");
     }
+
+    /// Export lxc image and check that written files hash to the fingerprint from the image listing.
+    ///
+    /// `image` is `[<remote>:]<alias or fingerprint>`, returns the full fingerprint.
+    pub fn export_verified_lxc_image(image: &str, name: &str) -> Result<String, LxcError> {
+      let fingerprint = resolve_lxc_image(image)?.fingerprint;
+
+      template_output("lxc", vec!["image".to_string(), "export".to_string(), image.to_string(), name.to_string()], "Failed to export image")?;
+
+      verify_exported_lxc_image(name, &fingerprint)
+    }
+
+    /// Check files written by `lxc image export <image> <name>` against the fingerprint
+    pub fn verify_exported_lxc_image(name: &str, fingerprint: &str) -> Result<String, LxcError> {
+      let target = Path::new(name);
+
+      let (metadata, rootfs) = if target.is_dir() {
+        find_exported_files(target, fingerprint)?
+      } else {
+        let dir = target.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        let file = target.file_name().map(|file| file.to_string_lossy().to_string()).unwrap_or_default();
+
+        find_exported_files(dir, &file)?
+      };
+
+      verify_image_fingerprint(&metadata, rootfs.as_deref(), fingerprint)
+    }
+
     
-    /// Import lxc image with alias
-    pub fn import_lxc_image(image: &str, import_name: &str) {
-      template("lxc", vec!["image".to_string(), "import".to_string(), image.to_string(), "--alias".to_string(), import_name.to_string()], "Failed to import image");
+    /// Import lxc image with alias, returns the fingerprint.
+    ///
+    /// Files named by their fingerprint are verified before import. For split images `image` is the
+    /// `meta-<fingerprint>` tarball and the rootfs is taken from the same directory.
+    pub fn import_lxc_image(image: &str, import_name: &str) -> Result<String, LxcError> {
+      let path = Path::new(image);
+      let is_split_metadata = path.file_name().map(|file| file.to_string_lossy().starts_with("meta-")).unwrap_or(false);
+
+      let fingerprint = match fingerprint_from_file_name(path) {
+        Some(fingerprint) => fingerprint,
+        None if is_split_metadata => return Err(LxcError::Invalid(format!("No fingerprint in the name of split image metadata {}", image))),
+        None => {
+          let fingerprint = image_fingerprint(path, None)?;
+          template_output("lxc", vec!["image".to_string(), "import".to_string(), image.to_string(), "--alias".to_string(), import_name.to_string()], "Failed to import image")?;
+          return Ok(fingerprint);
+        },
+      };
+
+      if !is_split_metadata {
+        return import_verified_lxc_image(image, None, &fingerprint, import_name);
+      }
+
+      let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+      let rootfs = match find_exported_files(dir, &fingerprint)? {
+        (_, Some(rootfs)) => rootfs,
+        (_, None) => return Err(LxcError::Invalid(format!("No rootfs of split image {} in {}", fingerprint, dir.display()))),
+      };
+
+      import_verified_lxc_image(image, Some(&rootfs.to_string_lossy()), &fingerprint, import_name)
+    }
+
+    /// Verify image files against the fingerprint and import them with alias
+    pub fn import_verified_lxc_image(metadata: &str, rootfs: Option<&str>, fingerprint: &str, alias: &str) -> Result<String, LxcError> {
+      let fingerprint = verify_image_fingerprint(Path::new(metadata), rootfs.map(Path::new), fingerprint)?;
+
+      let mut args = vec!["image".to_string(), "import".to_string(), metadata.to_string()];
+      args.extend(rootfs.map(|rootfs| rootfs.to_string()));
+      args.extend(["--alias".to_string(), alias.to_string()]);
+
+      template_output("lxc", args, "Failed to import image")?;
+
+      Ok(fingerprint)
+    }
+
+    /// Import image and return it as the remote lists it.
+    ///
+    /// `progress` gets upload progress updates. If the remote already has the fingerprint, only the aliases are
+    /// pointed at it and the properties, and `public` if asked for, applied to it. URL sources are downloaded with
+    /// curl to the temporary directory first.
+    pub fn import_lxc_image_with_progress(import: &ImageImport, progress: &mut dyn FnMut(ImportProgress)) -> Result<LxcImage, LxcError> {
+      static NEXT_DOWNLOAD: AtomicUsize = AtomicUsize::new(0);
+
+      let url = match &import.source {
+        ImageImportSource::Url(url) => url,
+        _ => return import_lxc_image_files(import, None, progress),
+      };
+
+      let file = std::env::temp_dir().join(format!("lxc-rust-import-{}-{}", std::process::id(), NEXT_DOWNLOAD.fetch_add(1, Ordering::Relaxed)));
+
+      let result = template_output("curl", vec!["-fsSL".to_string(), "-o".to_string(), file.to_string_lossy().to_string(), url.to_string()], "Failed to download image")
+        .and_then(|_| import_lxc_image_files(import, Some(&file), progress));
+
+      let _ = std::fs::remove_file(file);
+
+      result
+    }
+
+    fn import_lxc_image_files(import: &ImageImport, download: Option<&Path>, progress: &mut dyn FnMut(ImportProgress)) -> Result<LxcImage, LxcError> {
+      let (metadata, rootfs) = match (&import.source, download) {
+        (ImageImportSource::Unified(metadata), _) => (metadata.as_path(), None),
+        (ImageImportSource::Split { metadata, rootfs }, _) => (metadata.as_path(), Some(rootfs.as_path())),
+        (ImageImportSource::Url(_), Some(file)) => (file, None),
+        (ImageImportSource::Url(url), None) => return Err(LxcError::Invalid(format!("Image {} was not downloaded", url))),
+      };
+
+      let fingerprint = image_fingerprint(metadata, rootfs)?;
+      let image = format!("{}:{}", import.remote, fingerprint);
+      let exists = get_matching_lxc_images(&import.remote, &fingerprint, None, &ImageFilter::new())?.iter().any(|image| image.fingerprint == fingerprint);
+
+      if exists {
+        for alias in &import.aliases {
+          promote_remote_image_alias(&import.remote, alias, &fingerprint)?;
+        }
+
+        for (key, value) in &import.properties {
+          set_image_property(&image, key, value)?;
+        }
+
+        if import.public {
+          edit_lxc_image(&image, &ImageSettings::new().public(true))?;
+        }
+      } else {
+        // Under the pseudo terminal lxc writes its errors to the same stream as the progress
+        let mut last_message = String::new();
+        let mut on_line = |line: &str| match parse_progress(line) {
+          Some(update) => progress(update),
+          None if !line.trim().is_empty() => last_message = line.trim().to_string(),
+          None => (),
+        };
+
+        template_lines("script", pty_args("lxc", &import.to_args(metadata, rootfs)), &mut on_line, "Failed to import image").map_err(|e| match e {
+          LxcError::Command { message, stderr } if stderr.is_empty() => LxcError::Command { message, stderr: last_message },
+          e => e,
+        })?;
+      }
+
+      resolve_lxc_image(&image)
+    }
+
+    /// Validate and import image made by `api::image_builder::ImageBuilder`, returns the fingerprint
+    pub fn import_built_lxc_image(image: &BuiltImage, alias: &str) -> Result<String, LxcError> {
+      validate_image_tarballs(&image.metadata_path, image.rootfs_path.as_deref())?;
+
+      let metadata = image.metadata_path.to_string_lossy();
+      let rootfs = image.rootfs_path.as_ref().map(|rootfs| rootfs.to_string_lossy());
+
+      import_verified_lxc_image(&metadata, rootfs.as_deref(), &image.fingerprint, alias)
+    }
+    
+    /// Convert OCI image layout to an LXD image in `output` and import it with alias, returns the fingerprint
+    pub fn import_oci_lxc_image(layout: &str, reference: Option<&str>, output: &str, alias: &str) -> Result<String, LxcError> {
+      let image = build_oci_image(Path::new(layout), reference, Path::new(output))?;
+
+      import_built_lxc_image(&image, alias)
     }
     
     /// Delete lxc image
-    pub fn del_lxc_image(image: &str) {
-      template("lxc", vec!["image".to_string(), "delete".to_string(), image.to_string()], "Try of delete image was failed");
+    pub fn del_lxc_image(image: &str) -> Result<(), LxcError> {
+      del_project_lxc_image(image, None)
+    }
+
+    /// Delete lxc image of the project, `image` is `[<remote>:]<alias or fingerprint>`
+    pub fn del_project_lxc_image(image: &str, project: Option<&str>) -> Result<(), LxcError> {
+      let mut args = vec!["image".to_string(), "delete".to_string(), resolved_project_lxc_image_arg(image, project)?];
+      args.extend(project.iter().flat_map(|project| ["--project".to_string(), project.to_string()]));
+
+      template_output("lxc", args, "Try of delete image was failed")?;
+
+      Ok(())
+    }
+
+    /// Collect local images which have no alias and no instance was created from, in every project with its own images.
+    ///
+    /// Returns the images the policy collected: deleted ones, or the ones it would delete on a dry run.
+    /// Instances of all projects count as references, so an image is kept while any of them uses its fingerprint.
+    pub fn gc_local_lxc_images(policy: &GcPolicy) -> Result<Vec<GcCandidate>, LxcError> {
+      let projects: Vec<serde_json::Value> = serde_json::from_value(query("local", "/1.0/projects?recursion=1", "Failed to get projects")?)?;
+      let instances = template_output("lxc", vec!["list".to_string(), "local:".to_string(), "--all-projects".to_string(), "--format".to_string(), "json".to_string()], "Failed to get instances")?;
+      let instances: Vec<serde_json::Value> = serde_json::from_str(&instances)?;
+      let base_images = base_images(&instances);
+
+      let mut collected = Vec::new();
+
+      for project in image_projects(&projects) {
+        let images = get_matching_lxc_images("local", "", Some(&project), &ImageFilter::new())?;
+
+        for candidate in plan_gc(&project, &images, &base_images, policy, now()).into_iter().filter(|candidate| candidate.collectable) {
+          if !policy.dry_run {
+            del_project_lxc_image(&format!("local:{}", candidate.fingerprint), Some(&project))?;
+          }
+
+          collected.push(candidate);
+        }
+      }
+
+      Ok(collected)
     }
     
     /// Refresh lxc image
-    pub fn refresh_lxc_image(image: &str) {
-      template("lxc", vec!["image".to_string(), "refresh".to_string(), image.to_string()], "Failed to refresh a current image");
+    pub fn refresh_lxc_image(image: &str) -> Result<(), LxcError> {
+      template_output("lxc", vec!["image".to_string(), "refresh".to_string(), resolved_lxc_image_arg(image)?], "Failed to refresh a current image")?;
+
+      Ok(())
     }
     
     /// Set property to image
-    pub fn set_image_property(image: &str, key: &str, value: &str) {
-      template("lxc", vec!["image".to_string(), "set-property".to_string(), image.to_string(), key.to_string(), value.to_string()], "Failed to set image property");
+    pub fn set_image_property(image: &str, key: &str, value: &str) -> Result<(), LxcError> {
+      template_output("lxc", vec!["image".to_string(), "set-property".to_string(), resolved_lxc_image_arg(image)?, key.to_string(), value.to_string()], "Failed to set image property")?;
+
+      Ok(())
     }
     
     /// Unset property from image
-    pub fn unset_image_property(image: &str, key: &str) {
-      template("lxc", vec!["image".to_string(), "unset-property".to_string(), image.to_string(), key.to_string()], "Failed to unset image property");
+    pub fn unset_image_property(image: &str, key: &str) -> Result<(), LxcError> {
+      template_output("lxc", vec!["image".to_string(), "unset-property".to_string(), resolved_lxc_image_arg(image)?, key.to_string()], "Failed to unset image property")?;
+
+      Ok(())
     }
     
-    /// Get image aliases
-    pub fn get_image_aliases() {
-      template("lxc", vec!["image".to_string(), "alias".to_string(), "list".to_string()], "Failed to get image aliases");
+    /// Set well-known property of the image, `image` is `[<remote>:]<alias or fingerprint>`
+    pub fn set_lxc_image_property(image: &str, property: ImageProperty, value: &str) -> Result<(), LxcError> {
+      let image = resolved_lxc_image_arg(image)?;
+
+      template_output("lxc", vec!["image".to_string(), "set-property".to_string(), image, property.name().to_string(), value.to_string()], "Failed to set image property")?;
+
+      Ok(())
+    }
+
+    pub fn unset_lxc_image_property(image: &str, property: ImageProperty) -> Result<(), LxcError> {
+      let image = resolved_lxc_image_arg(image)?;
+
+      template_output("lxc", vec!["image".to_string(), "unset-property".to_string(), image, property.name().to_string()], "Failed to unset image property")?;
+
+      Ok(())
+    }
+
+    /// Change `auto_update`, `public` and `expires_at` of the image with `lxc image edit`
+    pub fn edit_lxc_image(image: &str, settings: &ImageSettings) -> Result<(), LxcError> {
+      let image = resolved_lxc_image_arg(image)?;
+      let yaml = template_output("lxc", vec!["image".to_string(), "show".to_string(), image.to_string()], "Failed to show image")?;
+
+      template_input("lxc", vec!["image".to_string(), "edit".to_string(), image], &settings.apply(&yaml)?, "Failed to edit image")?;
+
+      Ok(())
+    }
+
+    /// Report when cached images of local server expire and auto-updating ones refresh
+    pub fn get_local_image_lifecycle() -> Result<Vec<ImageLifecycle>, LxcError> {
+      get_remote_image_lifecycle("local")
+    }
+
+    pub fn get_remote_image_lifecycle(remote: &str) -> Result<Vec<ImageLifecycle>, LxcError> {
+      // Defaults of LXD when the keys are not set
+      let auto_update_interval = match get_remote_server_config_value(remote, ServerConfigKey::ImagesAutoUpdateInterval)? {
+        Some(ServerConfig::ImagesAutoUpdateInterval(hours)) => hours,
+        _ => 6,
+      };
+
+      let remote_cache_expiry = match get_remote_server_config_value(remote, ServerConfigKey::ImagesRemoteCacheExpiry)? {
+        Some(ServerConfig::ImagesRemoteCacheExpiry(days)) => days,
+        _ => 10,
+      };
+
+      Ok(image_lifecycle(&get_remote_lxc_images(remote, &ImageFilter::new())?, auto_update_interval, remote_cache_expiry, now()))
+    }
+
+    /// Get image aliases of local server
+    pub fn get_local_image_aliases() -> Result<Vec<ImageAlias>, LxcError> {
+      get_remote_image_aliases("local")
+    }
+
+    /// Get image aliases of remote server
+    pub fn get_remote_image_aliases(remote: &str) -> Result<Vec<ImageAlias>, LxcError> {
+      let output = template_output("lxc", vec!["image".to_string(), "alias".to_string(), "list".to_string(), format!("{}:", remote.to_string()), "--format".to_string(), "json".to_string()], "Failed to get image aliases")?;
+
+      Ok(serde_json::from_str(&output)?)
+    }
+
+    pub fn get_remote_image_alias(remote: &str, alias: &str) -> Result<Option<ImageAlias>, LxcError> {
+      Ok(get_remote_image_aliases(remote)?.into_iter().find(|candidate| candidate.name == alias))
     }
     
-    /// Create image alias
-    pub fn create_image_alias(alias: &str, fingerprint: &str) {
-      template("lxc", vec!["image".to_string(), "create".to_string(), "create".to_string(), alias.to_string(), fingerprint.to_string()], "Failed to create image alias");
+    /// Create image alias pointing to the image, `image` is an alias, fingerprint or fingerprint prefix
+    pub fn create_local_image_alias(alias: &str, image: &str, description: &str) -> Result<ImageAlias, LxcError> {
+      create_remote_image_alias("local", alias, image, description)
+    }
+
+    pub fn create_remote_image_alias(remote: &str, alias: &str, image: &str, description: &str) -> Result<ImageAlias, LxcError> {
+      let target = resolve_lxc_image(&format!("{}:{}", remote, image))?;
+      let alias = ImageAlias { name: alias.to_string(), target: target.fingerprint, description: description.to_string(), tp: target.tp };
+      let body = serde_json::json!({ "name": alias.name, "target": alias.target, "description": alias.description, "type": alias.tp.to_string() });
+
+      template_output("lxc", vec!["query".to_string(), "-X".to_string(), "POST".to_string(), "-d".to_string(), body.to_string(), format!("{}:/1.0/images/aliases", remote.to_string())], "Failed to create image alias")?;
+
+      Ok(alias)
     }
     
-    /// Delete image alias
-    pub fn delete_image_alias(alias: &str) {
-      template("lxc", vec!["image".to_string(), "delete".to_string(), alias.to_string()], "Failed to delete image alias");
+    /// Delete image alias, the image stays
+    pub fn delete_local_image_alias(alias: &str) -> Result<(), LxcError> {
+      delete_remote_image_alias("local", alias)
+    }
+
+    pub fn delete_remote_image_alias(remote: &str, alias: &str) -> Result<(), LxcError> {
+      template_output("lxc", vec!["image".to_string(), "alias".to_string(), "delete".to_string(), format!("{}:{}", remote.to_string(), alias.to_string())], "Failed to delete image alias")?;
+
+      Ok(())
     }
     
     /// Rename image alias
-    pub fn rename_image_alias(old_name: &str, new_name: &str) {
-      template("lxc", vec!["image".to_string(), "rename".to_string(), old_name.to_string(), new_name.to_string()], "Failed to rename image alias"); 
+    pub fn rename_local_image_alias(old_name: &str, new_name: &str) -> Result<(), LxcError> {
+      rename_remote_image_alias("local", old_name, new_name)
+    }
+
+    pub fn rename_remote_image_alias(remote: &str, old_name: &str, new_name: &str) -> Result<(), LxcError> {
+      template_output("lxc", vec!["image".to_string(), "alias".to_string(), "rename".to_string(), format!("{}:{}", remote.to_string(), old_name.to_string()), new_name.to_string()], "Failed to rename image alias")?;
+
+      Ok(())
+    }
+
+    /// Point existing alias at another image in one update, instances launched from the alias never see it missing
+    pub fn retarget_local_image_alias(alias: &str, image: &str) -> Result<ImageAlias, LxcError> {
+      retarget_remote_image_alias("local", alias, image)
+    }
+
+    pub fn retarget_remote_image_alias(remote: &str, alias: &str, image: &str) -> Result<ImageAlias, LxcError> {
+      let target = resolve_lxc_image(&format!("{}:{}", remote, image))?;
+      let current = get_remote_image_alias(remote, alias)?.ok_or_else(|| LxcError::Invalid(format!("Image alias {} not found", alias)))?;
+      let body = serde_json::json!({ "target": target.fingerprint });
+
+      template_output("lxc", vec!["query".to_string(), "-X".to_string(), "PATCH".to_string(), "-d".to_string(), body.to_string(), format!("{}:/1.0/images/aliases/{}", remote.to_string(), alias.to_string())], "Failed to retarget image alias")?;
+
+      Ok(ImageAlias { target: target.fingerprint, tp: target.tp, ..current })
+    }
+
+    /// Make the alias point to a new build: retarget it if it exists, create it otherwise.
+    ///
+    /// Returns fingerprint the alias pointed to before, e.g. to roll back or garbage collect it.
+    pub fn promote_local_image_alias(alias: &str, image: &str) -> Result<Option<String>, LxcError> {
+      promote_remote_image_alias("local", alias, image)
+    }
+
+    pub fn promote_remote_image_alias(remote: &str, alias: &str, image: &str) -> Result<Option<String>, LxcError> {
+      match get_remote_image_alias(remote, alias)? {
+        Some(current) => {
+          retarget_remote_image_alias(remote, alias, image)?;
+          Ok(Some(current.target))
+        },
+        None => {
+          create_remote_image_alias(remote, alias, image, "")?;
+          Ok(None)
+        },
+      }
     }
   }
 
   // Container
   pub mod container {
-    use crate::template::template;
+    use std::collections::HashMap;
+    use crate::api::cluster::ClusterTarget;
+    use crate::api::instance::{Instance, InstanceSpec, InstanceSnapshot, InstanceState};
+    use std::process;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+    use crate::api::exec::{ExecCommand, ExecOutput};
+    use crate::api::rest;
+    use crate::error::LxcError;
+    use crate::template::{template, template_output, query};
+
+    /// Timeout of single requests to the socket, waiting for a command to finish has none
+    const REST_TIMEOUT: Duration = Duration::from_secs(30);
     
     /// Get local lxc containers
     pub fn get_local_lxc() {
@@ -196,6 +1019,140 @@ pub use snapshot::*;
     pub fn launch_remote_lxc(remote: &str, image: &str, container: &str) {
       template("lxc", vec!["launch".to_string(), format!("{}:{}", remote.to_string(), image.to_string()), format!("{}:{}", remote.to_string(), container.to_string())], "Failed of launching remote container was failed");
     }
+
+    /// Launch new lxc container on the cluster member or group
+    pub fn launch_remote_lxc_on_target(remote: &str, image: &str, container: &str, target: &ClusterTarget) -> Result<(), LxcError> {
+      let mut args = vec!["launch".to_string(), format!("{}:{}", remote.to_string(), image.to_string()), format!("{}:{}", remote.to_string(), container.to_string())];
+      args.extend(target.to_args());
+
+      template_output("lxc", args, "Failed to launch container on cluster target")?;
+
+      Ok(())
+    }
+    
+    /// Create instance from the spec without starting it, the instance is deleted again if a device can't be added
+    pub fn init_lxc_instance(spec: &InstanceSpec) -> Result<Instance, LxcError> {
+      template_output("lxc", spec.init_args(), "Failed to create instance")?;
+
+      for args in spec.device_args() {
+        if let Err(e) = template_output("lxc", args, "Failed to add instance device") {
+          let _ = template_output("lxc", spec.delete_args(), "Failed to delete half configured instance");
+          return Err(e);
+        }
+      }
+
+      get_remote_lxc_instance(&spec.remote, &spec.name, spec.project.as_deref())
+    }
+
+    /// Create instance from the spec and start it
+    pub fn launch_lxc_instance(spec: &InstanceSpec) -> Result<Instance, LxcError> {
+      init_lxc_instance(spec)?;
+
+      let mut args = vec!["start".to_string(), format!("{}:{}", spec.remote, spec.name)];
+      args.extend(spec.project_args());
+
+      template_output("lxc", args, "Failed to start instance")?;
+
+      get_remote_lxc_instance(&spec.remote, &spec.name, spec.project.as_deref())
+    }
+
+    pub fn get_local_lxc_instance(name: &str, project: Option<&str>) -> Result<Instance, LxcError> {
+      get_remote_lxc_instance("local", name, project)
+    }
+
+    pub fn get_remote_lxc_instance(remote: &str, name: &str, project: Option<&str>) -> Result<Instance, LxcError> {
+      let path = match project {
+        Some(project) => format!("/1.0/instances/{}?project={}", name, project),
+        None => format!("/1.0/instances/{}", name),
+      };
+
+      Ok(serde_json::from_value(query(remote, &path, "Failed to get instance")?)?)
+    }
+    
+    /// Run command in the instance through the exec API of the local server and capture its output.
+    ///
+    /// The exit code comes from the finished operation and is returned in `ExecOutput`, errors of LXD such as a
+    /// stopped instance, an unknown user or a missing working directory are `LxcError`s. Non-empty stdin is
+    /// pushed to a file in the instance, which is removed afterwards.
+    pub fn exec(command: &ExecCommand) -> Result<ExecOutput, LxcError> {
+      static NEXT_STDIN: AtomicUsize = AtomicUsize::new(0);
+
+      if command.remote != "local" {
+        return Err(LxcError::Invalid(format!("exec reaches only the local server, not {}", command.remote)));
+      }
+
+      if command.stdin.is_empty() {
+        return run_exec(command, None);
+      }
+
+      let file = format!("/tmp/.lxc-rust-stdin-{}-{}", process::id(), NEXT_STDIN.fetch_add(1, Ordering::Relaxed));
+      let push = command.instance_path(&format!("/files?path={}", file));
+      let headers = command.stdin_file_headers();
+      let headers: Vec<(&str, &str)> = headers.iter().map(|(name, value)| (*name, value.as_str())).collect();
+
+      rest::post_bytes(&push, &headers, &command.stdin, REST_TIMEOUT)?;
+
+      let result = run_exec(command, Some(&file));
+      let _ = rest::delete(&push, REST_TIMEOUT);
+
+      result
+    }
+
+    fn run_exec(command: &ExecCommand, stdin_file: Option<&str>) -> Result<ExecOutput, LxcError> {
+      let operation = rest::post(&command.instance_path("/exec"), &command.to_request(stdin_file), REST_TIMEOUT)?;
+      let operation = rest::wait_operation(&operation, None)?;
+      let metadata = &operation["metadata"];
+
+      let exit_code = metadata["return"].as_i64().ok_or_else(|| LxcError::Parse("Exec operation has no return code".to_string()))?;
+      let stdout = read_exec_log(command, &metadata["output"]["1"]);
+      let stderr = read_exec_log(command, &metadata["output"]["2"]);
+
+      Ok(ExecOutput { stdout: stdout?, stderr: stderr?, exit_code: exit_code as i32 })
+    }
+
+    /// Read output LXD recorded for the command and delete the log
+    fn read_exec_log(command: &ExecCommand, log: &serde_json::Value) -> Result<Vec<u8>, LxcError> {
+      let log = command.api_path(log.as_str().ok_or_else(|| LxcError::Parse("Exec operation has no recorded output".to_string()))?);
+      let raw = rest::get_raw(&log, REST_TIMEOUT)?;
+      let (code, body) = rest::split_response(&raw)?;
+
+      if code >= 400 {
+        rest::parse_response(&raw)?;
+      }
+
+      let output = body.to_vec();
+      let _ = rest::delete(&log, REST_TIMEOUT);
+
+      Ok(output)
+    }
+
+    /// Get typed state of lxc container with its snapshots
+    pub fn get_local_lxc_state(container: &str) -> Result<InstanceState, LxcError> {
+      get_remote_lxc_state("local", container)
+    }
+
+    pub fn get_remote_lxc_state(remote: &str, container: &str) -> Result<InstanceState, LxcError> {
+      let mut state: InstanceState = serde_json::from_value(query(remote, &format!("/1.0/instances/{}/state", container), "Failed to get instance state")?)?;
+      let snapshots: Option<Vec<InstanceSnapshot>> = serde_json::from_value(query(remote, &format!("/1.0/instances/{}/snapshots?recursion=1", container), "Failed to get instance snapshots")?)?;
+
+      state.snapshots = snapshots.unwrap_or_default();
+
+      Ok(state)
+    }
+
+    /// Get states of all lxc containers by name in one listing
+    pub fn get_local_lxc_states() -> Result<HashMap<String, InstanceState>, LxcError> {
+      get_remote_lxc_states("local")
+    }
+
+    pub fn get_remote_lxc_states(remote: &str) -> Result<HashMap<String, InstanceState>, LxcError> {
+      let output = template_output("lxc", vec!["list".to_string(), format!("{}:", remote.to_string()), "--format".to_string(), "json".to_string()], "Failed to get instances")?;
+      let entries: Vec<serde_json::Value> = serde_json::from_str(&output)?;
+
+      entries.iter()
+        .map(|entry| Ok((entry["name"].as_str().unwrap_or_default().to_string(), InstanceState::from_list_entry(entry)?)))
+        .collect()
+    }
     
     /// Get information about lxc container
     pub fn get_local_lxc_info(container: &str) {
@@ -244,7 +1201,17 @@ pub use snapshot::*;
     
     /// Rename remote lxc container
     pub fn rename_remote_lxc(remote: &str, container: &str, new_name: &str) {
-      template("lxc", vec!["move".to_string()], "Failed to rename remote linux container");
+      template("lxc", vec!["move".to_string(), format!("{}:{}", remote.to_string(), container.to_string()), format!("{}:{}", remote.to_string(), new_name.to_string())], "Failed to rename remote linux container");
+    }
+
+    /// Move lxc container to another cluster member or group
+    pub fn move_remote_lxc_to_target(remote: &str, container: &str, target: &ClusterTarget) -> Result<(), LxcError> {
+      let mut args = vec!["move".to_string(), format!("{}:{}", remote.to_string(), container.to_string())];
+      args.extend(target.to_args());
+
+      template_output("lxc", args, "Failed to move container to cluster target")?;
+
+      Ok(())
     }
     
     /// Restart lxc container
@@ -262,7 +1229,17 @@ pub use snapshot::*;
     }
 
     pub fn copy_remote_lxc(remote: &str, container: &str, to_container: &str) {
-      template("lxc", vec!["copy".to_string(), format!("{}:{}", remote.to_string(), container.to_string()), format!("{}:{}", remote.to_string(), container.to_string())], "Failed to copy from remote first container to second");
+      template("lxc", vec!["copy".to_string(), format!("{}:{}", remote.to_string(), container.to_string()), format!("{}:{}", remote.to_string(), to_container.to_string())], "Failed to copy from remote first container to second");
+    }
+
+    /// Copy lxc container to the cluster member or group
+    pub fn copy_remote_lxc_to_target(remote: &str, container: &str, to_container: &str, target: &ClusterTarget) -> Result<(), LxcError> {
+      let mut args = vec!["copy".to_string(), format!("{}:{}", remote.to_string(), container.to_string()), format!("{}:{}", remote.to_string(), to_container.to_string())];
+      args.extend(target.to_args());
+
+      template_output("lxc", args, "Failed to copy container to cluster target")?;
+
+      Ok(())
     }
    
     /// Get lxc configuration
@@ -356,7 +1333,9 @@ pub use snapshot::*;
   }
 
   pub mod volume {
-    use crate::template::template;
+    use crate::api::cluster::ClusterTarget;
+    use crate::error::LxcError;
+    use crate::template::{template, template_output};
     
     /// Get volumes by current storage
     pub fn get_volumes_by_storage(storage: &str) {
@@ -375,6 +1354,16 @@ pub use snapshot::*;
     pub fn create_remote_volume(remote: &str, storage: &str, name: &str) {
       template("lxc", vec!["storage".to_string(), "volume".to_string(), "create".to_string(), format!("{}:{}", remote.to_string(), storage.to_string()), name.to_string()], "Failed to create remote volume");
     }
+
+    /// Create volume on the cluster member or group
+    pub fn create_remote_volume_on_target(remote: &str, storage: &str, name: &str, target: &ClusterTarget) -> Result<(), LxcError> {
+      let mut args = vec!["storage".to_string(), "volume".to_string(), "create".to_string(), format!("{}:{}", remote.to_string(), storage.to_string()), name.to_string()];
+      args.extend(target.to_args());
+
+      template_output("lxc", args, "Failed to create volume on cluster target")?;
+
+      Ok(())
+    }
     
     /// Attach volume from current storage
     pub fn attach_volume_lxc(storage: &str, volume: &str, container: &str, path: &str) {
@@ -685,7 +1674,11 @@ pub use snapshot::*;
 
   // Config
   pub mod config {
-    use crate::template::template;
+    use std::collections::HashMap;
+    use crate::api::image_metadata::ImageMetadata;
+    use crate::api::server_config::{parse_server_config, ServerConfig, ServerConfigKey};
+    use crate::error::LxcError;
+    use crate::template::{template, template_output, template_input, query};
     
     /// Set config property
     pub fn set_config_property(key: &str, value: &str) {
@@ -746,6 +1739,48 @@ pub use snapshot::*;
     pub fn show_config_metadata(fingerprint: &str) {
       template("lxc", vec!["config".to_string(), "metadata".to_string(), "show".to_string(), fingerprint.to_string()], "Failed to get config metadatas by current fingerprint");
     }
+
+    /// Get metadata.yaml of the container
+    pub fn get_local_lxc_metadata(container: &str) -> Result<ImageMetadata, LxcError> {
+      get_remote_lxc_metadata("local", container)
+    }
+
+    pub fn get_remote_lxc_metadata(remote: &str, container: &str) -> Result<ImageMetadata, LxcError> {
+      let yaml = template_output("lxc", vec!["config".to_string(), "metadata".to_string(), "show".to_string(), format!("{}:{}", remote.to_string(), container.to_string())], "Failed to get container metadata")?;
+
+      ImageMetadata::from_yaml(&yaml)
+    }
+
+    /// Replace metadata.yaml of the container
+    pub fn set_local_lxc_metadata(container: &str, metadata: &ImageMetadata) -> Result<(), LxcError> {
+      set_remote_lxc_metadata("local", container, metadata)
+    }
+
+    pub fn set_remote_lxc_metadata(remote: &str, container: &str, metadata: &ImageMetadata) -> Result<(), LxcError> {
+      template_input("lxc", vec!["config".to_string(), "metadata".to_string(), "edit".to_string(), format!("{}:{}", remote.to_string(), container.to_string())], &metadata.to_yaml()?, "Failed to edit container metadata")?;
+
+      Ok(())
+    }
+
+    /// Get content of the container template file
+    pub fn get_local_lxc_template(container: &str, title: &str) -> Result<String, LxcError> {
+      get_remote_lxc_template("local", container, title)
+    }
+
+    pub fn get_remote_lxc_template(remote: &str, container: &str, title: &str) -> Result<String, LxcError> {
+      template_output("lxc", vec!["config".to_string(), "template".to_string(), "show".to_string(), format!("{}:{}", remote.to_string(), container.to_string()), title.to_string()], "Failed to get container template")
+    }
+
+    /// Replace content of the container template file, the template has to exist
+    pub fn set_local_lxc_template(container: &str, title: &str, content: &str) -> Result<(), LxcError> {
+      set_remote_lxc_template("local", container, title, content)
+    }
+
+    pub fn set_remote_lxc_template(remote: &str, container: &str, title: &str, content: &str) -> Result<(), LxcError> {
+      template_input("lxc", vec!["config".to_string(), "template".to_string(), "edit".to_string(), format!("{}:{}", remote.to_string(), container.to_string()), title.to_string()], content, "Failed to edit container template")?;
+
+      Ok(())
+    }
     
     /// Get devices by current config
     pub fn get_config_devices(fingerprint: &str) {
@@ -771,6 +1806,53 @@ pub use snapshot::*;
     pub fn get_config_device_details(fingerprint: &str) {
       template("lxc", vec!["config".to_string(), "device".to_string(), "show".to_string(), fingerprint.to_string()], "Failed to get config device configuration details");
     }
+
+    /// Get whole local server configuration
+    pub fn get_local_server_config() -> Result<HashMap<String, String>, LxcError> {
+      get_remote_server_config("local")
+    }
+
+    pub fn get_remote_server_config(remote: &str) -> Result<HashMap<String, String>, LxcError> {
+      let server = query(remote, "/1.0", "Failed to get server configuration")?;
+
+      Ok(parse_server_config(&server))
+    }
+
+    /// Get value of the server configuration key, `None` if it is not set
+    pub fn get_local_server_config_value(key: ServerConfigKey) -> Result<Option<ServerConfig>, LxcError> {
+      get_remote_server_config_value("local", key)
+    }
+
+    pub fn get_remote_server_config_value(remote: &str, key: ServerConfigKey) -> Result<Option<ServerConfig>, LxcError> {
+      match get_remote_server_config(remote)?.get(key.name()) {
+        Some(value) if !value.is_empty() => ServerConfig::parse(key, value).map(Some),
+        _ => Ok(None),
+      }
+    }
+
+    /// Validate and set server configuration key
+    pub fn set_local_server_config(config: &ServerConfig) -> Result<(), LxcError> {
+      set_remote_server_config("local", config)
+    }
+
+    pub fn set_remote_server_config(remote: &str, config: &ServerConfig) -> Result<(), LxcError> {
+      config.validate()?;
+
+      template_output("lxc", vec!["config".to_string(), "set".to_string(), format!("{}:", remote.to_string()), config.key().name().to_string(), config.value()], "Failed to set server configuration key")?;
+
+      Ok(())
+    }
+
+    /// Unset server configuration key
+    pub fn unset_local_server_config_key(key: ServerConfigKey) -> Result<(), LxcError> {
+      unset_remote_server_config_key("local", key)
+    }
+
+    pub fn unset_remote_server_config_key(remote: &str, key: ServerConfigKey) -> Result<(), LxcError> {
+      template_output("lxc", vec!["config".to_string(), "unset".to_string(), format!("{}:", remote.to_string()), key.name().to_string()], "Failed to unset server configuration key")?;
+
+      Ok(())
+    }
   }
 
   // Remote connection
@@ -781,6 +1863,11 @@ pub use snapshot::*;
     pub fn connect_to_remote_registry(name: &str, address: &str) {
       template("lxc", vec!["remote".to_string(), "add".to_string(), name.to_string(), address.to_string()], "Failed to connect to remote lxc");
     }
+
+    /// Connect to simplestreams image server, e.g. one served by `api::image_server`
+    pub fn connect_to_simplestreams_registry(name: &str, address: &str) {
+      template("lxc", vec!["remote".to_string(), "add".to_string(), name.to_string(), address.to_string(), "--protocol".to_string(), "simplestreams".to_string()], "Failed to connect to simplestreams remote");
+    }
     
     /// Rename remote registry
     pub fn rename_remote_registry(instance: &str, title: &str) {